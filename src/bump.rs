@@ -0,0 +1,219 @@
+//! Computing release versions from bump keywords.
+
+use crate::config::Config;
+use std::{
+    str::FromStr,
+    fmt::{self, Display, Formatter},
+};
+use semver::{Version, Identifier};
+use failure::{Error, format_err};
+
+/// A semver component to bump.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+    Pre,
+}
+
+/// Version to release, given either exactly or as a bump.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VersionSpec {
+    Exact(Version),
+    Bump(Bump),
+}
+
+impl Bump {
+    /// Compute the version after a previous release.
+    ///
+    /// Bumping a prerelease to the level it's already a
+    /// prerelease of just drops the prerelease, so
+    /// `1.2.0-rc.1` bumps by minor to `1.2.0`. `Pre` bumps the
+    /// last numeric prerelease identifier, or starts a new
+    /// prerelease of the next patch.
+    pub fn apply(self, prev: &Version) -> Version {
+        let mut next = prev.clone();
+        next.build.clear();
+        match self {
+            Bump::Patch => {
+                if next.is_prerelease() {
+                    next.pre.clear();
+                } else {
+                    next.increment_patch();
+                }
+            },
+            Bump::Minor => {
+                if next.is_prerelease() && next.patch == 0 {
+                    next.pre.clear();
+                } else {
+                    next.increment_minor();
+                }
+            },
+            Bump::Major => {
+                if next.is_prerelease() && next.patch == 0 && next.minor == 0 {
+                    next.pre.clear();
+                } else {
+                    next.increment_major();
+                }
+            },
+            Bump::Pre => {
+                if !next.is_prerelease() {
+                    next.increment_patch();
+                    next.pre = vec![Identifier::Numeric(0)];
+                } else if let Some(&mut Identifier::Numeric(ref mut n)) = next.pre.last_mut() {
+                    *n += 1;
+                } else {
+                    next.pre.push(Identifier::Numeric(0));
+                }
+            },
+        };
+        next
+    }
+}
+
+/// Find the release to bump from, which is the newest release
+/// tag, given the manifest version. Since the manifest has the 
+/// after-release suffix after a release, both must agree.
+pub fn bump_base(
+    config: &Config,
+    package_name: &str,
+    tagged: Option<Version>,
+    manifest_version: &str,
+) -> Result<Version, Error> {
+    let after = match manifest_version.strip_suffix(&config.after_release_suffix) {
+        Some(after) => Some(Version::parse(after).map_err(|e| format_err!(
+            "Manifest version {:?} isn't semver: {}", manifest_version, e))?),
+        None => None,
+    };
+    match (tagged, after) {
+        (Some(tagged), Some(after)) if tagged == after => Ok(tagged),
+        (Some(tagged), Some(after)) => Err(format_err!(
+            "Newest release tag is {}, but manifest version {:?} is after {}",
+            config.release_tag(package_name, &tagged), manifest_version, after)),
+        (Some(tagged), None) => Err(format_err!(
+            "Newest release tag is {}, but manifest version {:?} is not after \
+            any release", config.release_tag(package_name, &tagged), manifest_version)),
+        (None, Some(after)) => Err(format_err!(
+            "Manifest version {:?} is after {}, but there is no release tag {}",
+            manifest_version, after, config.release_tag(package_name, &after))),
+        (None, None) => Err(format_err!(
+            "There are no releases to bump from, so the version must be given exactly")),
+    }
+}
+
+impl FromStr for Bump {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "patch" => Ok(Bump::Patch),
+            "minor" => Ok(Bump::Minor),
+            "major" => Ok(Bump::Major),
+            "pre" => Ok(Bump::Pre),
+            _ => Err(format_err!("invalid bump keyword {:?}", s)),
+        }
+    }
+}
+
+impl Display for Bump {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Bump::Patch => "patch",
+            Bump::Minor => "minor",
+            Bump::Major => "major",
+            Bump::Pre => "pre",
+        })
+    }
+}
+
+impl FromStr for VersionSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        s.parse::<Bump>()
+            .map(VersionSpec::Bump)
+            .or_else(|_| Version::parse(s)
+                .map(VersionSpec::Exact)
+                .map_err(|e| format_err!("{:?} is neither a version \
+                    nor a bump keyword:\n{}", s, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn bump(bump: Bump, prev: &str) -> String {
+        bump.apply(&Version::parse(prev).unwrap()).to_string()
+    }
+    
+    #[test]
+    fn bump_release() {
+        assert_eq!(bump(Bump::Patch, "1.2.3"), "1.2.4");
+        assert_eq!(bump(Bump::Minor, "1.2.3"), "1.3.0");
+        assert_eq!(bump(Bump::Major, "1.2.3"), "2.0.0");
+        assert_eq!(bump(Bump::Pre, "1.2.3"), "1.2.4-0");
+        assert_eq!(bump(Bump::Patch, "1.2.3+build.5"), "1.2.4");
+    }
+    
+    #[test]
+    fn bump_zero_major() {
+        assert_eq!(bump(Bump::Patch, "0.2.3"), "0.2.4");
+        assert_eq!(bump(Bump::Minor, "0.2.3"), "0.3.0");
+        assert_eq!(bump(Bump::Major, "0.2.3"), "1.0.0");
+        assert_eq!(bump(Bump::Pre, "0.0.0"), "0.0.1-0");
+    }
+    
+    #[test]
+    fn bump_prerelease() {
+        // bumping to the level it's a prerelease of releases it
+        assert_eq!(bump(Bump::Patch, "1.2.3-rc.1"), "1.2.3");
+        assert_eq!(bump(Bump::Minor, "1.2.0-rc.1"), "1.2.0");
+        assert_eq!(bump(Bump::Major, "2.0.0-rc.1"), "2.0.0");
+        // otherwise, it's bumped past
+        assert_eq!(bump(Bump::Minor, "1.2.3-rc.1"), "1.3.0");
+        assert_eq!(bump(Bump::Major, "1.2.0-rc.1"), "2.0.0");
+        
+        assert_eq!(bump(Bump::Pre, "1.2.0-rc.1"), "1.2.0-rc.2");
+        assert_eq!(bump(Bump::Pre, "1.2.0-9"), "1.2.0-10");
+        assert_eq!(bump(Bump::Pre, "1.2.0-rc"), "1.2.0-rc.0");
+    }
+    
+    #[test]
+    fn bump_base_after_release() {
+        let config = Config::default();
+        let tagged = Some(Version::parse("1.2.0").unwrap());
+        assert_eq!(bump_base(&config, "a", tagged, "1.2.0-AFTER").unwrap().to_string(), 
+            "1.2.0");
+        
+        let config = Config {
+            after_release_suffix: "-dev".to_owned(),
+            ..Config::default()
+        };
+        let tagged = Some(Version::parse("1.2.0-rc.1").unwrap());
+        assert_eq!(bump_base(&config, "a", tagged, "1.2.0-rc.1-dev").unwrap().to_string(), 
+            "1.2.0-rc.1");
+    }
+    
+    #[test]
+    fn bump_base_disagrees() {
+        let config = Config::default();
+        let error = |tagged: Option<&str>, manifest_version| bump_base(
+            &config, "a", tagged.map(|v| Version::parse(v).unwrap()), manifest_version,
+        ).unwrap_err().to_string();
+        
+        assert_eq!(error(Some("1.2.0"), "1.1.0-AFTER"), 
+            "Newest release tag is a-v1.2.0, but manifest version \"1.1.0-AFTER\" \
+            is after 1.1.0");
+        assert_eq!(error(Some("1.2.0"), "1.2.0"), 
+            "Newest release tag is a-v1.2.0, but manifest version \"1.2.0\" is not \
+            after any release");
+        assert_eq!(error(None, "1.2.0-AFTER"), 
+            "Manifest version \"1.2.0-AFTER\" is after 1.2.0, but there is no \
+            release tag a-v1.2.0");
+        assert_eq!(error(None, "0.1.0"), 
+            "There are no releases to bump from, so the version must be given exactly");
+        assert!(error(Some("1.2.0"), "1.2-AFTER").contains("isn't semver"));
+    }
+}
@@ -1,12 +1,15 @@
 
+use crate::config::PrereleaseNotes;
 use std::{
-    fs::read_to_string,
+    fs::{self, read_to_string},
     path::Path,
+    ops::Range,
     fmt::{self, Display, Formatter},
 };
 use regex::Regex;
+use pulldown_cmark::{Parser, Event, Tag};
 use semver::Version;
-use failure::Error;
+use failure::{Error, format_err};
 
 macro_rules! regexes {
     ($(
@@ -20,169 +23,1201 @@ macro_rules! regexes {
     };
 }
 
+/// A parsed changelog.
+///
+/// Headers are understood in the Keep a Changelog format, such
+/// as `## [1.2.0] - 2024-05-01`, as well as a plain `## 1.2.0`
+/// or `## v1.2.0`, optionally dated as `## 1.2.0 (2024-05-01)`.
+#[derive(Debug, Clone, Default)]
+pub struct Changelog {
+    /// The `## [Unreleased]` entry, if there is one.
+    pub unreleased: Option<UnreleasedNote>,
+    /// Version entries, in the order they appear.
+    pub versions: Vec<VersionNote>,
+    /// Headers which look like versions, but aren't semver,
+    /// with why.
+    pub invalid_headers: Vec<String>,
+    /// Byte offset and level of the first entry header.
+    first_entry: Option<(usize, u32)>,
+    /// End and level of the section header which the entries 
+    /// were read from, if any.
+    section: Option<(usize, u32)>,
+}
+
+impl Changelog {
+    /// Find the entry for a version, ignoring build metadata.
+    pub fn find(&self, version: &Version) -> Option<&VersionNote> {
+        self.versions.iter().find(|note| same_version(&note.version, version))
+    }
+    
+    /// Find the notes to release a version with, ignoring build
+    /// metadata. A prerelease may use the entry of its base 
+    /// version, by policy. The note records how it matched.
+    pub fn find_notes(
+        &self, 
+        version: &Version, 
+        prereleases: PrereleaseNotes,
+    ) -> Option<VersionNote> {
+        let own = || self.find(version)
+            .map(|note| match note.version.build == version.build {
+                true => (note, NoteMatch::Exact),
+                false => (note, NoteMatch::IgnoringBuild),
+            });
+        let base = || Some(version)
+            .filter(|version| version.is_prerelease())
+            .and_then(|version| self.find(&Version::new(
+                version.major, version.minor, version.patch)))
+            .map(|note| (note, NoteMatch::BaseVersion));
+        
+        let found = match prereleases {
+            PrereleaseNotes::Exact => own(),
+            PrereleaseNotes::Fallback => own().or_else(base),
+            PrereleaseNotes::Base if version.is_prerelease() => base(),
+            PrereleaseNotes::Base => own(),
+        };
+        found.map(|(note, matched)| VersionNote {
+            matched,
+            ..note.clone()
+        })
+    }
+    
+    /// Find the entry for the newest version.
+    pub fn newest(&self) -> Option<&VersionNote> {
+        self.versions.iter().max_by(|a, b| a.version.cmp(&b.version))
+    }
+    
+    /// Find problems with the changelog, given the versions
+    /// which were released.
+    ///
+    /// Versions newer than every release are expected to be
+    /// untagged, since they're yet to be released.
+    pub fn lint(&self, releases: &[Version], prereleases: PrereleaseNotes) -> Vec<String> {
+        let mut problems = self.invalid_headers.clone();
+        
+        for (i, note) in self.versions.iter().enumerate() {
+            if self.versions[..i].iter().any(|prev| prev.version == note.version) {
+                problems.push(format!("Version {} appears more than once", note.version));
+            } else if i > 0 && self.versions[i - 1].version < note.version {
+                problems.push(format!("Version {} is below {}, but is newer", 
+                    note.version, self.versions[i - 1].version));
+            }
+            if note.body.trim().is_empty() {
+                problems.push(format!("Version {} has no notes", note.version));
+            }
+        }
+        
+        let newest_release = releases.iter().max();
+        for (i, note) in self.versions.iter().enumerate() {
+            if self.versions[..i].iter().any(|prev| prev.version == note.version) {
+                continue;
+            }
+            let pending = newest_release
+                .map(|newest| &note.version > newest)
+                .unwrap_or(true);
+            if !pending && !releases.contains(&note.version) {
+                problems.push(format!("Version {} was never tagged as released", 
+                    note.version));
+            }
+        }
+        for release in releases {
+            if self.find_notes(release, prereleases).is_none() {
+                problems.push(format!("Release {} has no entry", release));
+            }
+        }
+        problems
+    }
+}
+
 /// An entry in a changelog.
 #[derive(Debug, Clone)]
 pub struct VersionNote {
     pub version: Version,
+    /// Release date, as written after the version.
+    pub date: Option<String>,
+    /// Whether the version is marked `[YANKED]`.
+    pub yanked: bool,
+    pub body: String,
+    /// Byte range of the entry in the changelog, from its 
+    /// header to the end of its last block.
+    pub span: Range<usize>,
+    /// How the entry matched the version it was found for.
+    pub matched: NoteMatch,
+}
+
+/// How a changelog entry matched a version.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NoteMatch {
+    /// The entry is for the version.
+    Exact,
+    /// The entry is for the version, with other build metadata.
+    IgnoringBuild,
+    /// The entry is for the base version of a prerelease.
+    BaseVersion,
+}
+
+/// The entry for unreleased changes in a changelog.
+#[derive(Debug, Clone)]
+pub struct UnreleasedNote {
     pub body: String,
+    /// Byte range of the entry in the changelog.
+    pub span: Range<usize>,
+    /// Byte range of the header, without its line break.
+    header: Range<usize>,
+    /// Byte range of the header's text.
+    title: Range<usize>,
 }
 
 impl Display for VersionNote {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.write_str("#### ")?;
         Display::fmt(&self.version, f)?;
+        if let Some(date) = self.date.as_ref() {
+            write!(f, " - {}", date)?;
+        }
+        if self.yanked {
+            f.write_str(" [YANKED]")?;
+        }
         f.write_str("\n\n")?;
-        f.write_str(&self.no_trailing_newline())?;
+        f.write_str(no_trailing_newline(&self.body))?;
+        Ok(())
+    }
+}
+
+impl Display for UnreleasedNote {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("#### Unreleased\n\n")?;
+        f.write_str(no_trailing_newline(&self.body))?;
         Ok(())
     }
 }
 
-impl VersionNote {
-    fn no_trailing_newline(&self) -> &str {
-        Some(self.body.len())
-            .filter(|&l| l > 0)
-            .map(|l| &self.body[..l - 1])
-            .unwrap_or("")
+impl Display for NoteMatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            NoteMatch::Exact => "exact",
+            NoteMatch::IgnoringBuild => "ignoring build metadata",
+            NoteMatch::BaseVersion => "base version",
+        })
     }
 }
 
-pub fn read_changelog<P>(path: P) -> Result<Vec<VersionNote>, Error> 
+/// Whether two versions are the same, ignoring build metadata.
+fn same_version(a: &Version, b: &Version) -> bool {
+    (a.major, a.minor, a.patch, &a.pre) == (b.major, b.minor, b.patch, &b.pre)
+}
+
+fn no_trailing_newline(body: &str) -> &str {
+    Some(body.len())
+        .filter(|&l| l > 0)
+        .map(|l| &body[..l - 1])
+        .unwrap_or("")
+}
+
+/// Read a changelog, or if there's a `section`, only the
+/// entries under the header of that name, such as `# my-crate`
+/// in a changelog shared by several crates.
+pub fn read_changelog<P>(path: P, section: Option<&str>) -> Result<Changelog, Error> 
 where
     P: AsRef<Path>
 {
     let data = read_to_string(&path).map_err(Error::from)?;
-    let mut parsed: Vec<(&str, ParsedLine)> = data
-        .lines()
-        .map(|line| (line, ParsedLine::from(line)))
-        .collect();
-        
-    for &mut (_, ref mut parsed) in &mut parsed {
-        parsed.specialize_header();
+    parse_section(&data, section, path.as_ref())
+}
+
+/// Parse a changelog, or a section of it, which must exist.
+fn parse_section(data: &str, section: Option<&str>, path: &Path) -> Result<Changelog, Error> {
+    let changelog = parse_changelog(data, section);
+    match (section, changelog.section) {
+        (Some(name), None) => Err(format_err!("changelog has no section \
+            for {:?} at:\n{:?}", name, path)),
+        _ => Ok(changelog),
+    }
+}
+
+/// Rename the unreleased entry of a changelog to a version, 
+/// optionally dated, and start a fresh unreleased entry above
+/// it. The rest of the file is kept byte-for-byte, including
+/// the header's markup, whether `##` or underlined.
+///
+/// Returns false, changing nothing, if the version already
+/// has an entry, so that it's only promoted once.
+pub fn promote_unreleased<P>(
+    path: P,
+    section: Option<&str>,
+    version: &Version,
+    date: Option<&str>,
+) -> Result<bool, Error>
+where
+    P: AsRef<Path>
+{
+    regexes! {
+        UNRELEASED = r#"(?i)unreleased"#;
     }
     
+    let data = read_to_string(&path).map_err(Error::from)?;
+    let changelog = parse_section(&data, section, path.as_ref())?;
+    if changelog.find(version).is_some() {
+        return Ok(false);
+    }
+    let unreleased = changelog.unreleased
+        .ok_or_else(|| format_err!("changelog has no unreleased entry \
+            at:\n{:?}", path.as_ref()))?;
+    let (range, title) = (unreleased.header, unreleased.title);
+    
+    let header = &data[range.clone()];
+    let word = UNRELEASED.find(&data[title.clone()]).unwrap();
+    let mut promoted = format!("{}{}{}", 
+        &data[title.start..title.start + word.start()], 
+        version,
+        &data[title.start + word.end()..title.end]);
+    if let Some(date) = date {
+        promoted = format!("{} - {}", promoted.trim_end(), date);
+    }
+    let newline = match data[range.end..].starts_with("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    
+    let mut edited = String::with_capacity(data.len() + header.len() + 32);
+    edited.push_str(&data[..range.start]);
+    edited.push_str(header);
+    edited.push_str(newline);
+    edited.push_str(newline);
+    edited.push_str(&data[range.start..title.start]);
+    edited.push_str(&promoted);
+    edited.push_str(&data[title.end..]);
+    fs::write(&path, edited).map_err(Error::from)?;
+    Ok(true)
+}
+
+/// Insert notes at the top of the unreleased entry of a 
+/// changelog, or of a section of it, starting one above the
+/// first entry if there is none. The rest of the file is kept
+/// byte-for-byte.
+pub fn insert_unreleased<P>(path: P, section: Option<&str>, notes: &str) -> Result<(), Error>
+where
+    P: AsRef<Path>
+{
+    let data = read_to_string(&path).map_err(Error::from)?;
+    let changelog = parse_section(&data, section, path.as_ref())?;
+    let newline = match data.contains("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    };
+    let notes = notes.trim().replace('\n', newline);
+    
+    let mut edited = String::with_capacity(data.len() + notes.len() + 32);
+    match (changelog.unreleased, changelog.first_entry) {
+        (Some(unreleased), _) => {
+            let end = unreleased.header.end;
+            edited.push_str(&data[..end]);
+            edited.push_str(newline);
+            edited.push_str(newline);
+            edited.push_str(&notes);
+            edited.push_str(&data[end..]);
+        },
+        (None, Some((start, pounds))) => {
+            edited.push_str(&data[..start]);
+            for _ in 0..pounds {
+                edited.push('#');
+            }
+            edited.push_str(" [Unreleased]");
+            edited.push_str(newline);
+            edited.push_str(newline);
+            edited.push_str(&notes);
+            edited.push_str(newline);
+            edited.push_str(newline);
+            edited.push_str(&data[start..]);
+        },
+        (None, None) if changelog.section.is_some() => {
+            let (end, level) = changelog.section.unwrap();
+            edited.push_str(&data[..end]);
+            edited.push_str(newline);
+            edited.push_str(newline);
+            for _ in 0..=level {
+                edited.push('#');
+            }
+            edited.push_str(" [Unreleased]");
+            edited.push_str(newline);
+            edited.push_str(newline);
+            edited.push_str(&notes);
+            edited.push_str(&data[end..]);
+        },
+        (None, None) => {
+            edited.push_str(&data);
+            if !data.is_empty() && !data.ends_with('\n') {
+                edited.push_str(newline);
+            }
+            if !data.trim().is_empty() {
+                edited.push_str(newline);
+            }
+            edited.push_str("## [Unreleased]");
+            edited.push_str(newline);
+            edited.push_str(newline);
+            edited.push_str(&notes);
+            edited.push_str(newline);
+        },
+    };
+    fs::write(&path, edited).map_err(Error::from)?;
+    Ok(())
+}
+
+fn parse_changelog(data: &str, section: Option<&str>) -> Changelog {
     let mut builder = LogBuilder {
-        accum: Vec::new(),
+        data,
+        accum: Changelog::default(),
         curr: None,
     };
+    // level of the section header, while within the section
+    let mut section_level: Option<u32> = None;
     
-    for &(line, ref parsed) in &parsed {
-        if let Some(ref mut curr) = builder.curr.as_mut() {
-            match parsed {
-                &ParsedLine::Header(pounds, _) => {
-                    if pounds >= curr.pounds {
-                        builder.finalize_curr();
-                    } else {
-                        curr.push_line(line);
-                    }
-                },
-                
-                &ParsedLine::VersionHeader(pounds, ref version) => {
-                    if pounds >= curr.pounds {
-                        builder.finalize_curr();
-                        builder.curr = Some(PartialEntry {
-                            version: version.clone(),
-                            pounds,
-                            body: "".to_owned(),
-                        });
-                    } else {
-                        curr.push_line(line);
-                    }
-                },
-                
-                &ParsedLine::SectionBreak => {
+    for block in parse_blocks(data) {
+        if let Some(name) = section {
+            match block {
+                Block::Heading { level, ref title, ref range } 
+                    if section_level.map(|l| level <= l).unwrap_or(true) => 
+                {
                     builder.finalize_curr();
+                    section_level = None;
+                    let title = data[title.clone()].trim()
+                        .trim_matches(|c| c == '`' || c == '[' || c == ']');
+                    if title == name && builder.accum.section.is_none() {
+                        section_level = Some(level);
+                        builder.accum.section = Some((range.end, level));
+                    }
+                    continue;
                 },
-                
-                &ParsedLine::Default => {
-                    curr.push_line(line);
-                },
-            };
-        } else {
-            match parsed {
-                &ParsedLine::VersionHeader(pounds, ref version) => {
-                    builder.curr = Some(PartialEntry {
-                        version: version.clone(),
-                        pounds,
-                        body: "".to_owned(),
-                    });
-                },
-                
+                _ if section_level.is_none() => continue,
                 _ => (),
             };
         }
+        
+        match block {
+            Block::Heading { level, title, range } => {
+                let header = match entry_header(&data[title.clone()]) {
+                    Ok(header) => header,
+                    Err(invalid) => {
+                        builder.accum.invalid_headers.push(invalid);
+                        None
+                    },
+                };
+                if header.is_some() && builder.accum.first_entry.is_none() {
+                    builder.accum.first_entry = Some((range.start, level));
+                }
+                
+                match builder.curr.as_mut() {
+                    // a subheader within the entry
+                    Some(curr) if level > curr.level => curr.push_block(range),
+                    _ => {
+                        builder.finalize_curr();
+                        builder.curr = header.map(|header| PartialEntry {
+                            header,
+                            range,
+                            title,
+                            level,
+                            body: None,
+                        });
+                    },
+                };
+            },
+            
+            Block::Rule => builder.finalize_curr(),
+            
+            Block::Other(range) => {
+                if let Some(curr) = builder.curr.as_mut() {
+                    curr.push_block(range);
+                }
+            },
+        };
     }
     
     builder.finalize_curr();
-    Ok(builder.accum)
+    builder.accum
 }
 
-struct LogBuilder {
-    accum: Vec<VersionNote>,
+/// A top-level Markdown block, with the byte range of its 
+/// source, without trailing whitespace.
+enum Block {
+    Heading {
+        level: u32,
+        /// Byte range of the heading's text.
+        title: Range<usize>,
+        range: Range<usize>,
+    },
+    /// Thematic break, such as `---`, which ends an entry.
+    Rule,
+    Other(Range<usize>),
+}
+
+/// Split a Markdown document into its top-level blocks. 
+///
+/// Link reference definitions, such as the version compare
+/// links at the end of a Keep a Changelog file, aren't blocks.
+fn parse_blocks(data: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut depth = 0;
+    let mut title: Option<Range<usize>> = None;
+    
+    for (event, range) in Parser::new(data).into_offset_iter() {
+        let range = trim_range(data, range);
+        match event {
+            Event::Start(_) => {
+                // links and emphasis are part of a heading's text
+                if depth > 0 {
+                    title = Some(match title.take() {
+                        Some(title) => title.start.min(range.start)..title.end.max(range.end),
+                        None => range,
+                    });
+                }
+                depth += 1;
+            },
+            Event::End(Tag::Heading(level)) if depth == 1 => {
+                depth -= 1;
+                blocks.push(Block::Heading {
+                    level,
+                    title: title.take().unwrap_or(range.end..range.end),
+                    range,
+                });
+            },
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    blocks.push(Block::Other(range));
+                }
+            },
+            Event::Rule if depth == 0 => blocks.push(Block::Rule),
+            // HTML blocks come as bare events
+            _ if depth == 0 => {
+                if !range.is_empty() {
+                    blocks.push(Block::Other(range));
+                }
+            },
+            // text within a heading, or some other block
+            _ => {
+                title = Some(match title.take() {
+                    Some(title) => title.start.min(range.start)..title.end.max(range.end),
+                    None => range,
+                });
+            },
+        };
+        if depth == 0 {
+            title = None;
+        }
+    }
+    blocks
+}
+
+fn trim_range(data: &str, range: Range<usize>) -> Range<usize> {
+    let trimmed = data[range.clone()].trim_end();
+    range.start..range.start + trimmed.len()
+}
+
+struct LogBuilder<'a> {
+    data: &'a str,
+    accum: Changelog,
     curr: Option<PartialEntry>
 }
 
 struct PartialEntry {
-    version: Version,
-    pounds: i32,
-    body: String,
+    header: EntryHeader,
+    /// Byte range of the header.
+    range: Range<usize>,
+    /// Byte range of the header's text.
+    title: Range<usize>,
+    level: u32,
+    /// Byte range of the body, from its first block to its last.
+    body: Option<Range<usize>>,
 }
 
-enum ParsedLine {
-    Header(i32, String),
-    VersionHeader(i32, Version),
-    SectionBreak,
-    Default,
+/// Header which starts a changelog entry.
+enum EntryHeader {
+    Version {
+        version: Version,
+        date: Option<String>,
+        yanked: bool,
+    },
+    Unreleased,
 }
 
-impl LogBuilder {
+impl LogBuilder<'_> {
     fn finalize_curr(&mut self) {
-        if let Some(mut curr) = self.curr.take() {
-            curr.body = curr.body.trim().to_owned();
-            curr.body.push('\n');
+        if let Some(curr) = self.curr.take() {
+            let mut body = curr.body.clone()
+                .map(|range| self.data[range].trim().replace("\r\n", "\n"))
+                .unwrap_or_default();
+            body.push('\n');
+            let span = curr.range.start..curr.body
+                .map(|range| range.end)
+                .unwrap_or(curr.range.end);
         
-            self.accum.push(VersionNote {
-                version: curr.version,
-                body: curr.body,
-            });
+            match curr.header {
+                EntryHeader::Version { version, date, yanked } => 
+                    self.accum.versions.push(VersionNote {
+                        version,
+                        date,
+                        yanked,
+                        body,
+                        span,
+                        matched: NoteMatch::Exact,
+                    }),
+                EntryHeader::Unreleased => {
+                    if self.accum.unreleased.is_some() {
+                        warn!("Changelog has several unreleased sections, \
+                            using the first");
+                    } else {
+                        self.accum.unreleased = Some(UnreleasedNote {
+                            body,
+                            span,
+                            header: curr.range,
+                            title: curr.title,
+                        });
+                    }
+                },
+            };
         }
     }
 }
 
 impl PartialEntry {
-    fn push_line(&mut self, line: &str) {
-        self.body.push_str(line);
-        self.body.push('\n');
+    fn push_block(&mut self, range: Range<usize>) {
+        self.body = Some(match self.body.take() {
+            Some(body) => body.start..range.end,
+            None => range,
+        });
     }
 }
 
-impl ParsedLine {
-    pub fn specialize_header(&mut self) {
-        if let &mut ParsedLine::Header(pounds, ref body) = self {
-            if let Ok(version) = Version::parse(body) {
-                *self = ParsedLine::VersionHeader(pounds, version);
-            }
+/// Parse the text of a header as an entry header, if it is 
+/// one. Fails for headers which look like versions, such as by
+/// starting with one, but aren't semver, or aren't in a form
+/// which is understood.
+fn entry_header(title: &str) -> Result<Option<EntryHeader>, String> {
+    regexes! {
+        VERSION_HEADER = r#"^\[?v?(?P<version>\d+\.\d+[^\]\s]*)\]?(?:\s+[-–]\s+(?P<date>\S+)|\s+\((?P<paren_date>[^)\s]+)\))?(?P<yanked>\s+\[YANKED\])?\s*$"#;
+        LOOKS_LIKE_VERSION = r#"^(?i)(?:version\s+)?\[?v?\d+\.\d+"#;
+        UNRELEASED_HEADER = r#"^(?i)\[?unreleased\]?\s*$"#;
+    }
+    
+    let title = title.trim();
+    if UNRELEASED_HEADER.is_match(title) {
+        Ok(Some(EntryHeader::Unreleased))
+    } else if let Some(caps) = VERSION_HEADER.captures(title) {
+        let version = caps.name("version").unwrap().as_str();
+        match Version::parse(version) {
+            Ok(version) => Ok(Some(EntryHeader::Version {
+                version,
+                date: caps.name("date").or_else(|| caps.name("paren_date"))
+                    .map(|date| date.as_str().to_owned()),
+                yanked: caps.name("yanked").is_some(),
+            })),
+            Err(e) => Err(format!("Header {:?} looks like a version, \
+                but isn't semver: {}", title, e)),
         }
+    } else if LOOKS_LIKE_VERSION.is_match(title) {
+        Err(format!("Header {:?} looks like a version, but isn't in \
+            a known form, such as \"1.2.0\", \"v1.2.0\", or \
+            \"[1.2.0] - 2024-05-01\"", title))
+    } else {
+        Ok(None)
     }
 }
 
-impl<S: AsRef<str>> From<S> for ParsedLine {
-    fn from(s: S) -> Self {
-        let line = s.as_ref();
-        
-        regexes! {
-            HEADER = r#"^(?P<pounds>#+)\s*(?P<body>.*)$"#;
-            SECTION_BREAK = r#"^\-\-\-+\s*$"#;
+/// Render Markdown as plain text, such as for tag messages and
+/// announcements. Lists keep their bullets, code blocks are 
+/// indented, headers are underlined, and links are followed by
+/// their URLs.
+pub fn plain_text(markdown: &str) -> String {
+    // ensure the output is at the start of a line, or after
+    // a blank line
+    fn break_line(out: &mut String) {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
         }
-        
-        if let Some(caps) = HEADER.captures(line) {
-            let body = caps.name("body").unwrap().as_str().to_owned();
-            let pounds = caps.name("pounds").unwrap().as_str().len() as i32;
+    }
+    fn break_block(out: &mut String) {
+        break_line(out);
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+    
+    let mut out = String::new();
+    // next number of each list, or none if unordered
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut code = false;
+    // where each open header, quote, and link starts
+    let mut starts: Vec<usize> = Vec::new();
+    
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Paragraph) if lists.is_empty() => break_block(&mut out),
+            Event::Start(Tag::Heading(_)) | Event::Start(Tag::BlockQuote) => {
+                break_block(&mut out);
+                starts.push(out.len());
+            },
+            Event::Start(Tag::CodeBlock(_)) => {
+                break_block(&mut out);
+                code = true;
+            },
+            Event::Start(Tag::List(first)) => {
+                match lists.is_empty() {
+                    true => break_block(&mut out),
+                    false => break_line(&mut out),
+                };
+                lists.push(first);
+            },
+            Event::Start(Tag::Item) => {
+                break_line(&mut out);
+                for _ in 1..lists.len() {
+                    out.push_str("  ");
+                }
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        out.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    },
+                    _ => out.push_str("- "),
+                };
+            },
+            Event::Start(Tag::Link(_, _, _)) => starts.push(out.len()),
             
-            ParsedLine::Header(pounds, body)
-        } else if SECTION_BREAK.is_match(line) {
-            ParsedLine::SectionBreak
-        } else {
-            ParsedLine::Default
+            Event::End(Tag::Heading(_)) => {
+                let start = starts.pop().unwrap_or(0);
+                let width = out[start..].chars().count();
+                out.push('\n');
+                out.push_str(&"-".repeat(width));
+                out.push('\n');
+            },
+            Event::End(Tag::BlockQuote) => {
+                let start = starts.pop().unwrap_or(0);
+                let quoted = out[start..].trim_end().lines()
+                    .map(|line| format!("> {}", line).trim_end().to_owned())
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                out.truncate(start);
+                out.push_str(&quoted);
+                out.push('\n');
+            },
+            Event::End(Tag::CodeBlock(_)) => {
+                code = false;
+                break_line(&mut out);
+            },
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                break_line(&mut out);
+            },
+            Event::End(Tag::Paragraph) => break_line(&mut out),
+            Event::End(Tag::Link(_, url, _)) => {
+                let start = starts.pop().unwrap_or(0);
+                if out[start..] != *url {
+                    out.push_str(&format!(" <{}>", url));
+                }
+            },
+            
+            Event::Text(text) if code => {
+                for line in text.split_inclusive('\n') {
+                    if out.is_empty() || out.ends_with('\n') {
+                        out.push_str("    ");
+                    }
+                    out.push_str(line);
+                }
+            },
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => out.push('\n'),
+            Event::Rule => {
+                break_block(&mut out);
+                out.push_str("---\n");
+            },
+            _ => (),
+        };
+    }
+    
+    let mut out = out.trim().to_owned();
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestDir;
+    
+    /// Write a changelog in a fresh directory, promote its
+    /// unreleased entry to 1.2.0, and return it as saved.
+    fn promote(name: &str, data: &str, date: Option<&str>) -> String {
+        let dir = TestDir::new(name);
+        let path = dir.write("CHANGELOG.md", data);
+        
+        let version = Version::parse("1.2.0").unwrap();
+        assert!(promote_unreleased(&path, None, &version, date).unwrap());
+        dir.read("CHANGELOG.md")
+    }
+    
+    /// Parse a changelog with one entry, under a header, and
+    /// return its version, date, and whether it's yanked, or
+    /// nothing if it's the unreleased entry.
+    fn header(header: &str) -> Option<(String, Option<String>, bool)> {
+        let changelog = parse_changelog(&format!("{}\n\n- Notes.\n", header), None);
+        assert!(changelog.invalid_headers.is_empty(), "{:?}", changelog.invalid_headers);
+        match changelog.unreleased {
+            Some(unreleased) => {
+                assert!(changelog.versions.is_empty());
+                assert_eq!(unreleased.body, "- Notes.\n");
+                None
+            },
+            None => {
+                assert_eq!(changelog.versions.len(), 1, "no entry for {:?}", header);
+                let note = &changelog.versions[0];
+                assert_eq!(note.body, "- Notes.\n");
+                Some((note.version.to_string(), note.date.clone(), note.yanked))
+            },
         }
     }
+    
+    #[test]
+    fn parse_header_forms() {
+        let version = |date: Option<&str>, yanked| 
+            Some(("1.2.0".to_owned(), date.map(String::from), yanked));
+        assert_eq!(header("## 1.2.0"), version(None, false));
+        assert_eq!(header("## v1.2.0"), version(None, false));
+        assert_eq!(header("## [1.2.0]"), version(None, false));
+        assert_eq!(header("## [1.2.0] - 2024-05-01"), version(Some("2024-05-01"), false));
+        assert_eq!(header("## [1.2.0] - 2024-05-01 [YANKED]"), 
+            version(Some("2024-05-01"), true));
+        assert_eq!(header("## 1.2.0 [YANKED]"), version(None, true));
+        assert_eq!(header("## 1.2.0 (2024-05-01)"), version(Some("2024-05-01"), false));
+        assert_eq!(header("1.2.0\n====="), version(None, false));
+        assert_eq!(header("1.2.0 - 2024-05-01\n---"), version(Some("2024-05-01"), false));
+        assert_eq!(header("## [Unreleased]"), None);
+        assert_eq!(header("## Unreleased"), None);
+        assert_eq!(header("Unreleased\n----------"), None);
+    }
+    
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions.iter().map(|v| Version::parse(v).unwrap()).collect()
+    }
+    
+    #[test]
+    fn lint_invalid_headers() {
+        let changelog = parse_changelog("\
+## 1.2
+
+- Not semver.
+
+## Version 1.1.0
+
+- Not a known form.
+
+## 1.0.0 2024-01-01
+
+- Not a known form.
+
+## 0.1.0 (2023-01-01)
+
+- Fine.
+
+## 2024 roadmap
+
+- Not a version.
+", None);
+        let problems = changelog.lint(&versions(&["0.1.0"]), PrereleaseNotes::Exact);
+        assert_eq!(problems.len(), 3, "{:#?}", problems);
+        assert!(problems[0].starts_with("Header \"1.2\" looks like a version, but isn't semver"));
+        assert!(problems[1].starts_with("Header \"Version 1.1.0\" looks like a version, \
+            but isn't in a known form"));
+        assert!(problems[2].starts_with("Header \"1.0.0 2024-01-01\" looks like a version, \
+            but isn't in a known form"));
+        assert_eq!(changelog.versions.len(), 1);
+    }
+    
+    #[test]
+    fn lint_entries() {
+        let changelog = parse_changelog("\
+## 1.3.0
+
+- Not released yet.
+
+## 1.1.0
+
+- Out of order.
+
+## 1.2.0
+
+## 1.1.0
+
+- Twice.
+
+## 1.0.1
+
+- Never tagged.
+
+## 1.0.0
+
+- First release.
+", None);
+        let problems = changelog.lint(
+            &versions(&["1.0.0", "1.1.0", "1.2.0", "0.9.0"]), PrereleaseNotes::Exact);
+        assert_eq!(problems, vec![
+            "Version 1.2.0 is below 1.1.0, but is newer",
+            "Version 1.2.0 has no notes",
+            "Version 1.1.0 appears more than once",
+            "Version 1.0.1 was never tagged as released",
+            "Release 0.9.0 has no entry",
+        ]);
+    }
+    
+    #[test]
+    fn lint_clean() {
+        let changelog = parse_changelog("\
+## [Unreleased]
+
+## 1.1.0-rc.1
+
+- Not released yet.
+
+## 1.0.0
+
+- First release.
+", None);
+        assert!(changelog.lint(&versions(&["1.0.0"]), PrereleaseNotes::Exact).is_empty());
+        // with no releases, every entry is pending
+        assert!(changelog.lint(&[], PrereleaseNotes::Exact).is_empty());
+        // a release candidate's notes may be those of its base version
+        let releases = versions(&["1.0.0-rc.1"]);
+        assert_eq!(changelog.lint(&releases, PrereleaseNotes::Exact), 
+            vec!["Release 1.0.0-rc.1 has no entry"]);
+        assert!(changelog.lint(&releases, PrereleaseNotes::Fallback).is_empty());
+    }
+    
+    /// Write a changelog in a fresh directory, insert notes in
+    /// its unreleased entry, and return it as saved.
+    fn insert(name: &str, data: &str, section: Option<&str>) -> String {
+        let dir = TestDir::new(name);
+        let path = dir.write("CHANGELOG.md", data);
+        
+        insert_unreleased(&path, section, "### Added\n- Things.\n").unwrap();
+        dir.read("CHANGELOG.md")
+    }
+    
+    #[test]
+    fn insert_existing_unreleased() {
+        let inserted = insert("insert-existing", "\
+# Changelog
+
+## [Unreleased]
+
+- Other things.
+
+## 1.0.0
+
+- First release.
+", None);
+        assert_eq!(inserted, "\
+# Changelog
+
+## [Unreleased]
+
+### Added
+- Things.
+
+- Other things.
+
+## 1.0.0
+
+- First release.
+");
+    }
+    
+    #[test]
+    fn insert_new_unreleased() {
+        let inserted = insert("insert-new", "\
+# Changelog
+
+## 1.0.0
+
+- First release.
+", None);
+        assert_eq!(inserted, "\
+# Changelog
+
+## [Unreleased]
+
+### Added
+- Things.
+
+## 1.0.0
+
+- First release.
+");
+        assert_eq!(parse_changelog(&inserted, None).unreleased.unwrap().body, 
+            "### Added\n- Things.\n");
+        
+        // with no entries at all
+        assert_eq!(insert("insert-empty", "# Changelog\n", None), "\
+# Changelog
+
+## [Unreleased]
+
+### Added
+- Things.
+");
+        assert_eq!(insert("insert-blank", "", None), "\
+## [Unreleased]
+
+### Added
+- Things.
+");
+    }
+    
+    #[test]
+    fn insert_new_unreleased_section() {
+        let inserted = insert("insert-section", "\
+# a
+
+# b
+
+## 1.0.0
+
+- First release.
+", Some("a"));
+        assert_eq!(inserted, "\
+# a
+
+## [Unreleased]
+
+### Added
+- Things.
+
+# b
+
+## 1.0.0
+
+- First release.
+");
+        let changelog = parse_changelog(&inserted, Some("a"));
+        assert!(changelog.unreleased.is_some());
+        assert!(changelog.versions.is_empty());
+    }
+    
+    /// Find the notes for a version, and return which entry's 
+    /// notes they are, and how they matched.
+    fn notes(version: &str, prereleases: PrereleaseNotes) -> Option<(String, NoteMatch)> {
+        let changelog = parse_changelog("\
+## 1.2.0
+
+- Release.
+
+## 1.2.0-rc.1
+
+- Release candidate.
+
+## 1.1.0+build.5
+
+- Built.
+", None);
+        changelog.find_notes(&Version::parse(version).unwrap(), prereleases)
+            .map(|note| (note.body.trim().to_owned(), note.matched))
+    }
+    
+    #[test]
+    fn find_ignoring_build() {
+        let changelog = parse_changelog("## 1.2.0\n\n- Release.\n", None);
+        let found = |version| changelog.find(&Version::parse(version).unwrap())
+            .map(|note| note.version.to_string());
+        assert_eq!(found("1.2.0"), Some("1.2.0".to_owned()));
+        assert_eq!(found("1.2.0+build.5"), Some("1.2.0".to_owned()));
+        assert_eq!(found("1.2.0-rc.1"), None);
+        assert_eq!(found("1.2.1"), None);
+        
+        let matched = |body: &str, matched| Some((body.to_owned(), matched));
+        assert_eq!(notes("1.2.0+build.5", PrereleaseNotes::Exact), 
+            matched("- Release.", NoteMatch::IgnoringBuild));
+        assert_eq!(notes("1.1.0", PrereleaseNotes::Exact), 
+            matched("- Built.", NoteMatch::IgnoringBuild));
+        assert_eq!(notes("1.1.0+build.5", PrereleaseNotes::Exact), 
+            matched("- Built.", NoteMatch::Exact));
+    }
+    
+    #[test]
+    fn find_notes_by_policy() {
+        let matched = |body: &str, matched| Some((body.to_owned(), matched));
+        let release = matched("- Release.", NoteMatch::Exact);
+        let candidate = matched("- Release candidate.", NoteMatch::Exact);
+        let base = matched("- Release.", NoteMatch::BaseVersion);
+        
+        // exact
+        assert_eq!(notes("1.2.0", PrereleaseNotes::Exact), release);
+        assert_eq!(notes("1.2.0-rc.1", PrereleaseNotes::Exact), candidate);
+        assert_eq!(notes("1.2.0-rc.2", PrereleaseNotes::Exact), None);
+        // fallback, only to prereleases with no entry
+        assert_eq!(notes("1.2.0", PrereleaseNotes::Fallback), release);
+        assert_eq!(notes("1.2.0-rc.1", PrereleaseNotes::Fallback), candidate);
+        assert_eq!(notes("1.2.0-rc.2", PrereleaseNotes::Fallback), base);
+        assert_eq!(notes("1.2.0-rc.2+build.5", PrereleaseNotes::Fallback), base);
+        assert_eq!(notes("1.3.0-rc.1", PrereleaseNotes::Fallback), None);
+        // base, for every prerelease
+        assert_eq!(notes("1.2.0", PrereleaseNotes::Base), release);
+        assert_eq!(notes("1.2.0-rc.1", PrereleaseNotes::Base), base);
+        assert_eq!(notes("1.2.0-rc.2", PrereleaseNotes::Base), base);
+        assert_eq!(notes("1.3.0-rc.1", PrereleaseNotes::Base), None);
+    }
+    
+    #[test]
+    fn parse_linked_headers() {
+        let changelog = parse_changelog("\
+# Changelog
+
+## [Unreleased]
+
+- Coming soon.
+
+## [1.1.0] - 2024-05-01
+
+- Added things.
+
+## [1.0.0] - 2024-01-01 [YANKED]
+
+- First release.
+
+[Unreleased]: https://example.com/compare/v1.1.0...HEAD
+[1.1.0]: https://example.com/compare/v1.0.0...v1.1.0
+[1.0.0]: https://example.com/releases/v1.0.0
+", None);
+        assert_eq!(changelog.unreleased.unwrap().body.trim(), "- Coming soon.");
+        let versions: Vec<(String, Option<String>, bool)> = changelog.versions.iter()
+            .map(|note| (note.version.to_string(), note.date.clone(), note.yanked))
+            .collect();
+        assert_eq!(versions, vec![
+            ("1.1.0".to_owned(), Some("2024-05-01".to_owned()), false),
+            ("1.0.0".to_owned(), Some("2024-01-01".to_owned()), true),
+        ]);
+    }
+    
+    #[test]
+    fn parse_fenced_comment() {
+        let changelog = parse_changelog("\
+## 1.1.0
+
+Configure it with:
+
+```sh
+# not a header
+deet check
+```
+
+- Added things.
+
+## 1.0.0
+
+- First release.
+", None);
+        assert_eq!(changelog.versions.len(), 2);
+        let body = &changelog.versions[0].body;
+        assert!(body.contains("# not a header"));
+        assert!(body.contains("- Added things."));
+    }
+    
+    #[test]
+    fn parse_setext_headers() {
+        let changelog = parse_changelog("\
+Changelog
+=========
+
+1.1.0
+-----
+
+- Added things.
+
+1.0.0
+-----
+
+- First release.
+", None);
+        let versions: Vec<String> = changelog.versions.iter()
+            .map(|note| note.version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["1.1.0", "1.0.0"]);
+        assert_eq!(changelog.versions[0].body.trim(), "- Added things.");
+    }
+    
+    #[test]
+    fn parse_html_block() {
+        let changelog = parse_changelog("\
+## 1.1.0
+
+<details>
+## 0.9.0
+</details>
+
+- Added things.
+", None);
+        let versions: Vec<String> = changelog.versions.iter()
+            .map(|note| note.version.to_string())
+            .collect();
+        assert_eq!(versions, vec!["1.1.0"]);
+        assert!(changelog.versions[0].body.contains("- Added things."));
+    }
+    
+    #[test]
+    fn promote_linked_unreleased() {
+        let promoted = promote("linked", "\
+# Changelog
+
+## [Unreleased]
+
+- Added things.
+
+## [1.1.0] - 2024-01-01
+
+- First release.
+
+[unreleased]: https://example.com/compare/v1.1.0...HEAD
+[1.1.0]: https://example.com/releases/v1.1.0
+", Some("2024-05-01"));
+        assert_eq!(promoted, "\
+# Changelog
+
+## [Unreleased]
+
+## [1.2.0] - 2024-05-01
+
+- Added things.
+
+## [1.1.0] - 2024-01-01
+
+- First release.
+
+[unreleased]: https://example.com/compare/v1.1.0...HEAD
+[1.1.0]: https://example.com/releases/v1.1.0
+");
+    }
+    
+    #[test]
+    fn promote_setext_unreleased() {
+        let promoted = promote("setext", "\
+Unreleased
+----------
+
+- Added things.
+
+1.1.0
+-----
+
+- First release.
+", None);
+        assert_eq!(promoted, "\
+Unreleased
+----------
+
+1.2.0
+----------
+
+- Added things.
+
+1.1.0
+-----
+
+- First release.
+");
+    }
 }
@@ -0,0 +1,568 @@
+//! Per-project DEET policies.
+//!
+//! Settings are read from a `deet.toml` at the root of
+//! the repo, then from the `[package.metadata.deet]` table
+//! of the package's manifest, which takes precedence.
+
+use crate::maniflect::ManifestFile;
+use std::{
+    path::{Path, PathBuf},
+    fs::read_to_string,
+    collections::HashMap,
+    str::FromStr,
+    fmt::{self, Display, Formatter},
+};
+use toml_edit::{DocumentMut, Item};
+use regex::Regex;
+use semver::{Version, VersionReq};
+use failure::{Error, format_err};
+
+/// Name of the config file at the root of a repo.
+pub const CONFIG_FILE: &str = "deet.toml";
+
+/// Loaded DEET configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Branch which releases are made from.
+    pub branch: String,
+    /// Remote which releases are pulled from and pushed to.
+    pub remote: String,
+    /// Format of release tags, with `{name}` and `{version}`
+    /// placeholders.
+    pub tag_format: String,
+    /// Formats of release tags from before `tag_format`, 
+    /// which are still read, but never created.
+    pub legacy_tag_formats: Vec<String>,
+    /// Suffix put on the manifest version after a release.
+    pub after_release_suffix: String,
+    /// Name of the changelog file.
+    pub changelog: String,
+    /// Where each package's changelog is.
+    pub changelog_layout: ChangelogLayout,
+    /// Whether to date the changelog entry which unreleased 
+    /// changes are promoted to.
+    pub changelog_date: bool,
+    /// Sections of drafted changelog entries, in order, with
+    /// a pattern matching the subjects of commits in each.
+    pub changelog_groups: Vec<(String, String)>,
+    /// Which changelog entry a prerelease's notes come from.
+    pub prerelease_notes: PrereleaseNotes,
+    /// Commands run in the package to check it before release.
+    pub checks: Vec<String>,
+    /// What to do with path-only dev-dependencies.
+    pub dev_deps: DevDepPolicy,
+    /// How to report local dependencies which changed since
+    /// their latest release.
+    pub unreleased_deps: LintLevel,
+    /// How to report local dependencies whose version 
+    /// requirement doesn't match their latest release.
+    pub stale_deps: LintLevel,
+    /// How to report releasing a version which skips versions
+    /// after the previous release.
+    pub skipped_versions: LintLevel,
+    /// Whether to keep the version requirements which local
+    /// dependencies were published with, alongside their 
+    /// paths, after a release.
+    pub persist_dep_versions: bool,
+    /// How to require the release of a delocalized dependency.
+    pub version_req: ReqStrategy,
+    /// Overrides of `version_req`, by dependency package name.
+    pub dep_version_reqs: HashMap<String, ReqStrategy>,
+}
+
+/// How to form the version requirement on a release of a
+/// delocalized dependency.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ReqStrategy {
+    /// `^{version}`
+    Caret,
+    /// `~{version}`
+    Tilde,
+    /// `={version}`
+    Exact,
+    /// Custom template, with `{version}`, `{major}`, `{minor}`,
+    /// and `{patch}` placeholders.
+    Template(String),
+}
+
+impl ReqStrategy {
+    /// Get the template, with placeholders.
+    pub fn template(&self) -> &str {
+        match self {
+            ReqStrategy::Caret => "^{version}",
+            ReqStrategy::Tilde => "~{version}",
+            ReqStrategy::Exact => "={version}",
+            ReqStrategy::Template(template) => template,
+        }
+    }
+    
+    /// Form the version requirement on a version.
+    pub fn requirement(&self, version: &Version) -> Result<String, Error> {
+        let req = self.template()
+            .replace("{version}", &version.to_string())
+            .replace("{major}", &version.major.to_string())
+            .replace("{minor}", &version.minor.to_string())
+            .replace("{patch}", &version.patch.to_string());
+        VersionReq::parse(&req)
+            .map_err(|e| format_err!("version requirement {:?} from {} \
+                is invalid: {}", req, self, e))?;
+        Ok(req)
+    }
+}
+
+impl FromStr for ReqStrategy {
+    type Err = Error;
+    
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "caret" => Ok(ReqStrategy::Caret),
+            "tilde" => Ok(ReqStrategy::Tilde),
+            "exact" => Ok(ReqStrategy::Exact),
+            template if template.contains('{') => 
+                Ok(ReqStrategy::Template(template.to_owned())),
+            _ => Err(format_err!("version requirement strategy {:?} is \
+                not caret, tilde, exact, or a template", s)),
+        }
+    }
+}
+
+impl Display for ReqStrategy {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ReqStrategy::Caret => f.write_str("caret"),
+            ReqStrategy::Tilde => f.write_str("tilde"),
+            ReqStrategy::Exact => f.write_str("exact"),
+            ReqStrategy::Template(template) => write!(f, "template {:?}", template),
+        }
+    }
+}
+
+/// How to report a problem which isn't always one.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LintLevel {
+    /// Just log it.
+    Allow,
+    /// Log a warning, which fails checks of a version.
+    Warn,
+    /// Log an error, which fails every check.
+    Deny,
+}
+
+impl LintLevel {
+    /// Log a problem at this level.
+    pub fn report(self, message: fmt::Arguments) {
+        match self {
+            LintLevel::Allow => info!("{}", message),
+            LintLevel::Warn => warn!("{}", message),
+            LintLevel::Deny => error!("{}", message),
+        };
+    }
+}
+
+/// What to do with dev-dependencies which only have a path.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DevDepPolicy {
+    /// Leave them path-only, so that `cargo publish` drops
+    /// them from the published manifest.
+    Drop,
+    /// Delocalize them, like other dependencies.
+    Convert,
+}
+
+/// Where each package's changelog is.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ChangelogLayout {
+    /// A changelog file in each package directory.
+    Package,
+    /// One changelog file at the root of the repo, with a 
+    /// section for each package, under a header of its name.
+    Shared,
+    /// A path relative to the root of the repo, with a
+    /// `{name}` placeholder.
+    Pattern(String),
+}
+
+/// Which changelog entry a prerelease's notes come from.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PrereleaseNotes {
+    /// Its own entry, such as `## 1.0.0-rc.2`.
+    Exact,
+    /// Its own entry if there is one, or else the entry of its
+    /// base version, such as `## 1.0.0`.
+    Fallback,
+    /// The entry of its base version.
+    Base,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            branch: "main".into(),
+            remote: "origin".into(),
+            tag_format: "{name}-v{version}".into(),
+            legacy_tag_formats: Vec::new(),
+            after_release_suffix: "-AFTER".into(),
+            changelog: "CHANGELOG.md".into(),
+            changelog_layout: ChangelogLayout::Package,
+            changelog_date: false,
+            changelog_groups: Vec::new(),
+            prerelease_notes: PrereleaseNotes::Exact,
+            checks: vec![
+                "cargo check --color always".into(),
+                "cargo test --color always".into(),
+                "cargo doc --no-deps --document-private-items --color always".into(),
+            ],
+            dev_deps: DevDepPolicy::Drop,
+            unreleased_deps: LintLevel::Warn,
+            stale_deps: LintLevel::Warn,
+            skipped_versions: LintLevel::Warn,
+            persist_dep_versions: false,
+            version_req: ReqStrategy::Caret,
+            dep_version_reqs: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config for a repo, and optionally a package
+    /// within it.
+    pub fn load<P>(repo: P, manifest: Option<&ManifestFile>) -> Result<Self, Error>
+    where
+        P: AsRef<Path>
+    {
+        let mut config = Config::default();
+
+        let path = repo.as_ref().join(CONFIG_FILE);
+        if path.is_file() {
+            debug!("Reading config at:\n{:?}", path);
+            let toml = read_to_string(&path)
+                .map_err(Error::from)?
+                .parse::<DocumentMut>()
+                .map_err(Error::from)?;
+            config.apply(toml.as_table().iter(), &path)?;
+        }
+
+        if let Some(manifest) = manifest {
+            if let Some(metadata) = manifest.metadata("deet") {
+                debug!("Reading config in manifest at:\n{:?}", manifest.path());
+                let table = metadata.as_table_like()
+                    .ok_or_else(|| format_err!("metadata.deet is not a \
+                        table-like at:\n{:?}", manifest.path()))?;
+                config.apply(table.iter(), manifest.path())?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Override settings with those in a table.
+    fn apply<'a, I>(&mut self, table: I, path: &Path) -> Result<(), Error>
+    where
+        I: Iterator<Item=(&'a str, &'a Item)>
+    {
+        let path = PathBuf::from(path);
+        let string = |key: &str, item: &Item| item.as_str()
+            .map(String::from)
+            .ok_or_else(|| format_err!("config {} is not string \
+                at:\n{:?}", key, path));
+        let boolean = |key: &str, item: &Item| item.as_bool()
+            .ok_or_else(|| format_err!("config {} is not bool \
+                at:\n{:?}", key, path));
+        let lint_level = |key: &str, item: &Item| match string(key, item)?.as_str() {
+            "allow" => Ok(LintLevel::Allow),
+            "warn" => Ok(LintLevel::Warn),
+            "deny" => Ok(LintLevel::Deny),
+            level => Err(format_err!("config {} is {:?}, not allow, warn, \
+                or deny, at:\n{:?}", key, level, path)),
+        };
+        let strings = |key: &str, item: &Item| item.as_array()
+            .and_then(|array| array.iter()
+                .map(|value| value.as_str().map(String::from))
+                .collect::<Option<Vec<String>>>())
+            .ok_or_else(|| format_err!("config {} is not an \
+                array of strings at:\n{:?}", key, path));
+        let tag_format = |key: &str, format: String| match format.contains("{version}") {
+            true => Ok(format),
+            false => Err(format_err!("config {} {:?} is missing a {{version}} \
+                placeholder at:\n{:?}", key, format, path)),
+        };
+
+        for (key, item) in table {
+            match key {
+                "branch" => self.branch = string(key, item)?,
+                "remote" => self.remote = string(key, item)?,
+                "tag-format" => self.tag_format = tag_format(key, string(key, item)?)?,
+                "legacy-tag-formats" => {
+                    self.legacy_tag_formats = strings(key, item)?.into_iter()
+                        .map(|format| tag_format(key, format))
+                        .collect::<Result<Vec<String>, Error>>()?;
+                },
+                "after-release-suffix" => self.after_release_suffix = string(key, item)?,
+                "changelog" => self.changelog = string(key, item)?,
+                "checks" => self.checks = strings(key, item)?,
+                "dev-dependencies" => {
+                    self.dev_deps = match string(key, item)?.as_str() {
+                        "drop" => DevDepPolicy::Drop,
+                        "convert" => DevDepPolicy::Convert,
+                        policy => return Err(format_err!("config dev-dependencies \
+                            is {:?}, not drop or convert, at:\n{:?}", policy, path)),
+                    };
+                },
+                "unreleased-dependencies" => self.unreleased_deps = lint_level(key, item)?,
+                "stale-dependencies" => self.stale_deps = lint_level(key, item)?,
+                "skipped-versions" => self.skipped_versions = lint_level(key, item)?,
+                "version-requirement" => {
+                    self.version_req = string(key, item)?.parse()
+                        .map_err(|e| format_err!("config {}: {}, at:\n{:?}", key, e, path))?;
+                },
+                "dependency-version-requirements" => {
+                    let table = item.as_table_like()
+                        .ok_or_else(|| format_err!("config {} is not a table-like \
+                            at:\n{:?}", key, path))?;
+                    for (package, item) in table.iter() {
+                        let strategy = string(key, item)?.parse()
+                            .map_err(|e| format_err!("config {}.{}: {}, at:\n{:?}", 
+                                key, package, e, path))?;
+                        self.dep_version_reqs.insert(package.to_owned(), strategy);
+                    }
+                },
+                "persist-dependency-versions" => self.persist_dep_versions = boolean(key, item)?,
+                "changelog-date" => self.changelog_date = boolean(key, item)?,
+                "changelog-layout" => {
+                    self.changelog_layout = match string(key, item)?.as_str() {
+                        "package" => ChangelogLayout::Package,
+                        "shared" => ChangelogLayout::Shared,
+                        pattern if pattern.contains("{name}") => 
+                            ChangelogLayout::Pattern(pattern.to_owned()),
+                        layout => return Err(format_err!("config changelog-layout \
+                            is {:?}, not package, shared, or a path with a {{name}} \
+                            placeholder, at:\n{:?}", layout, path)),
+                    };
+                },
+                "prerelease-notes" => {
+                    self.prerelease_notes = match string(key, item)?.as_str() {
+                        "exact" => PrereleaseNotes::Exact,
+                        "fallback" => PrereleaseNotes::Fallback,
+                        "base" => PrereleaseNotes::Base,
+                        policy => return Err(format_err!("config prerelease-notes \
+                            is {:?}, not exact, fallback, or base, at:\n{:?}", 
+                            policy, path)),
+                    };
+                },
+                "changelog-groups" => {
+                    let table = item.as_table_like()
+                        .ok_or_else(|| format_err!("config {} is not a table-like \
+                            at:\n{:?}", key, path))?;
+                    self.changelog_groups.clear();
+                    for (section, item) in table.iter() {
+                        let pattern = string(key, item)?;
+                        Regex::new(&pattern)
+                            .map_err(|e| format_err!("config {}.{}: {}, at:\n{:?}",
+                                key, section, e, path))?;
+                        self.changelog_groups.push((section.to_owned(), pattern));
+                    }
+                },
+                _ => warn!("Unknown config key {:?} at:\n{:?}", key, path),
+            };
+        }
+        Ok(())
+    }
+
+    /// Format the release tag for a version of a package.
+    pub fn release_tag(&self, package: &str, version: &Version) -> String {
+        format_tag(&self.tag_format, package, version)
+    }
+
+    /// Format the release tags for a version of a package, in
+    /// the current format, then each legacy format.
+    pub fn release_tags(&self, package: &str, version: &Version) -> Vec<String> {
+        self.tag_formats()
+            .map(|format| format_tag(format, package, version))
+            .collect()
+    }
+
+    /// Parse a release tag of a package, in the current format
+    /// or a legacy format.
+    pub fn parse_release_tag(&self, tag: &str, package: &str) -> Option<Version> {
+        self.tag_formats()
+            .find_map(|format| parse_tag(format, tag, package))
+    }
+
+    /// Parse a release tag of a package, only in the current
+    /// format.
+    pub fn parse_current_release_tag(&self, tag: &str, package: &str) -> Option<Version> {
+        parse_tag(&self.tag_format, tag, package)
+    }
+
+    /// Iterate the current tag format, then the legacy ones.
+    fn tag_formats(&self) -> impl Iterator<Item=&str> {
+        Some(self.tag_format.as_str()).into_iter()
+            .chain(self.legacy_tag_formats.iter().map(String::as_str))
+    }
+
+    /// How to require the release of a dependency package.
+    pub fn version_req(&self, package: &str) -> &ReqStrategy {
+        self.dep_version_reqs.get(package).unwrap_or(&self.version_req)
+    }
+
+    /// Get the changelog path of a package, within a repo.
+    pub fn changelog_path(&self, repo: &Path, package_path: &Path, package: &str) -> PathBuf {
+        match self.changelog_layout {
+            ChangelogLayout::Package => package_path.join(&self.changelog),
+            ChangelogLayout::Shared => repo.join(&self.changelog),
+            ChangelogLayout::Pattern(ref pattern) => 
+                repo.join(pattern.replace("{name}", package)),
+        }
+    }
+    
+    /// Get the section of its changelog which a package's 
+    /// entries are under, if it has one.
+    pub fn changelog_section<'a>(&self, package: &'a str) -> Option<&'a str> {
+        match self.changelog_layout {
+            ChangelogLayout::Shared => Some(package),
+            _ => None,
+        }
+    }
+
+    /// The manifest version after a release of some version.
+    pub fn after_release_version(&self, version: &Version) -> String {
+        format!("{}{}", version, self.after_release_suffix)
+    }
+}
+
+/// Format a release tag.
+fn format_tag(format: &str, package: &str, version: &Version) -> String {
+    format
+        .replace("{name}", package)
+        .replace("{version}", &version.to_string())
+}
+
+/// Parse a release tag in some format.
+fn parse_tag(format: &str, tag: &str, package: &str) -> Option<Version> {
+    let (prefix, suffix) = format
+        .replace("{name}", package)
+        .split_once("{version}")
+        .map(|(prefix, suffix)| (prefix.to_owned(), suffix.to_owned()))?;
+    tag.strip_prefix(&prefix)
+        .and_then(|s| s.strip_suffix(&suffix))
+        .and_then(|s| Version::parse(s).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestDir;
+    
+    /// Load the config from a deet.toml, in a fresh directory.
+    fn load(name: &str, toml: &str) -> Result<Config, String> {
+        let dir = TestDir::new(name);
+        dir.write(CONFIG_FILE, toml);
+        Config::load(dir.path(), None).map_err(|e| e.to_string())
+    }
+    
+    fn tag(config: &Config, tag: &str, package: &str) -> Option<String> {
+        config.parse_release_tag(tag, package).map(|version| version.to_string())
+    }
+    
+    #[test]
+    fn parse_default_tag_format() {
+        let config = Config::default();
+        assert_eq!(tag(&config, "a-v1.2.0", "a"), Some("1.2.0".to_owned()));
+        assert_eq!(tag(&config, "my-crate-v1.2.0-rc.1", "my-crate"), 
+            Some("1.2.0-rc.1".to_owned()));
+        assert_eq!(tag(&config, "my-crate-v1.2.0", "crate"), None);
+        assert_eq!(tag(&config, "b-v1.2.0", "a"), None);
+        assert_eq!(tag(&config, "a-v1.2", "a"), None);
+        assert_eq!(config.release_tag("a", &Version::parse("1.2.0").unwrap()), "a-v1.2.0");
+    }
+    
+    #[test]
+    fn parse_tag_formats() {
+        let config = load("tag-format-v", "tag-format = \"v{version}\"\n").unwrap();
+        assert_eq!(tag(&config, "v1.2.0", "a"), Some("1.2.0".to_owned()));
+        assert_eq!(tag(&config, "a-v1.2.0", "a"), None);
+        
+        let config = load("tag-format-name", "tag-format = \"{name}/{version}\"\n").unwrap();
+        assert_eq!(tag(&config, "a/1.2.0", "a"), Some("1.2.0".to_owned()));
+        assert_eq!(tag(&config, "b/1.2.0", "a"), None);
+        assert_eq!(tag(&config, "a/v1.2.0", "a"), None);
+    }
+    
+    #[test]
+    fn parse_legacy_tags() {
+        let config = load("tag-format-legacy", "\
+tag-format = \"{name}/{version}\"
+legacy-tag-formats = [\"v{version}\", \"{name}-{version}\"]
+").unwrap();
+        assert_eq!(tag(&config, "a/1.2.0", "a"), Some("1.2.0".to_owned()));
+        assert_eq!(tag(&config, "v1.1.0", "a"), Some("1.1.0".to_owned()));
+        assert_eq!(tag(&config, "a-1.0.0", "a"), Some("1.0.0".to_owned()));
+        // legacy tags are only read
+        assert_eq!(config.parse_current_release_tag("v1.1.0", "a"), None);
+        assert_eq!(config.parse_current_release_tag("a/1.2.0", "a"), 
+            Some(Version::parse("1.2.0").unwrap()));
+        assert_eq!(config.release_tags("a", &Version::parse("1.2.0").unwrap()), 
+            vec!["a/1.2.0", "v1.2.0", "a-1.2.0"]);
+        // matching no format
+        assert_eq!(tag(&config, "release-1.2.0", "a"), None);
+        assert_eq!(tag(&config, "a/latest", "a"), None);
+    }
+    
+    #[test]
+    fn tag_format_needs_version() {
+        let error = load("tag-format-invalid", "tag-format = \"{name}\"\n").unwrap_err();
+        assert!(error.starts_with("config tag-format \"{name}\" is missing a {version} \
+            placeholder"), "{}", error);
+        let error = load("tag-format-legacy-invalid", 
+            "legacy-tag-formats = [\"release\"]\n").unwrap_err();
+        assert!(error.starts_with("config legacy-tag-formats \"release\" is missing"), 
+            "{}", error);
+    }
+    
+    fn requirement(strategy: &str, version: &str) -> Result<String, String> {
+        strategy.parse::<ReqStrategy>()
+            .and_then(|strategy| strategy.requirement(&Version::parse(version).unwrap()))
+            .map_err(|e| e.to_string())
+    }
+    
+    #[test]
+    fn req_strategies() {
+        assert_eq!("caret".parse::<ReqStrategy>().unwrap(), ReqStrategy::Caret);
+        assert_eq!(requirement("caret", "1.2.3"), Ok("^1.2.3".to_owned()));
+        assert_eq!(requirement("tilde", "1.2.3"), Ok("~1.2.3".to_owned()));
+        assert_eq!(requirement("exact", "1.2.3-rc.1"), Ok("=1.2.3-rc.1".to_owned()));
+        assert_eq!(requirement("^{major}.{minor}", "1.2.3"), Ok("^1.2".to_owned()));
+        assert_eq!(requirement(">={version}, <{major}.{minor}.99", "0.2.3"), 
+            Ok(">=0.2.3, <0.2.99".to_owned()));
+    }
+    
+    #[test]
+    fn req_strategy_invalid() {
+        assert_eq!(requirement("^1.0", "1.2.3"), Err("version requirement strategy \
+            \"^1.0\" is not caret, tilde, exact, or a template".to_owned()));
+        assert_eq!(requirement("Caret", "1.2.3"), Err("version requirement strategy \
+            \"Caret\" is not caret, tilde, exact, or a template".to_owned()));
+        let error = requirement("^{name}", "1.2.3").unwrap_err();
+        assert!(error.starts_with("version requirement \"^{name}\" from template \
+            \"^{name}\" is invalid"), "{}", error);
+        
+        let error = load("req-strategy-invalid", "\
+[dependency-version-requirements]
+a = \"1.0\"
+").unwrap_err();
+        assert!(error.starts_with("config dependency-version-requirements.a: version \
+            requirement strategy \"1.0\" is not"), "{}", error);
+    }
+    
+    #[test]
+    fn req_strategy_per_dependency() {
+        let config = load("req-strategy", "\
+version-requirement = \"tilde\"
+
+[dependency-version-requirements]
+a-macros = \"exact\"
+").unwrap();
+        assert_eq!(config.version_req("a"), &ReqStrategy::Tilde);
+        assert_eq!(config.version_req("a-macros"), &ReqStrategy::Exact);
+    }
+}
@@ -0,0 +1,327 @@
+//! Drafting changelog entries from git history.
+//!
+//! Commits which effect a package since its latest release are
+//! grouped into changelog sections, by their Conventional
+//! Commit type, or by configured patterns.
+
+use crate::{
+    config::Config,
+    bump::Bump,
+    release::find_release,
+    util::{
+        cli::ResultExt,
+        cmd::preadlns,
+        git::{self, Commit},
+    },
+};
+use std::{
+    path::Path,
+    fmt::{self, Display, Formatter},
+};
+use regex::Regex;
+use semver::Version;
+use failure::Error;
+
+/// What kind of change a commit makes, by Conventional Commits.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ChangeKind {
+    Other,
+    Fix,
+    Feature,
+    Breaking,
+}
+
+/// A change made by a commit.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Description of the change, without any Conventional
+    /// Commit prefix.
+    pub summary: String,
+}
+
+/// A drafted changelog entry.
+#[derive(Debug, Clone)]
+pub struct Draft {
+    /// Release the changes are since, if any.
+    pub since: Option<Version>,
+    /// Sections, in order, with a line for each change.
+    pub sections: Vec<(String, Vec<String>)>,
+    /// Suggested bump from the release.
+    pub bump: Bump,
+}
+
+impl Change {
+    /// Classify a commit by its subject and body.
+    ///
+    /// A `!` after the type, or a `BREAKING CHANGE:` footer,
+    /// marks a breaking change. Other commits which don't 
+    /// follow Conventional Commits are `Other`.
+    pub fn parse(subject: &str, body: &str) -> Self {
+        lazy_static::lazy_static! {
+            static ref CONVENTIONAL: Regex = Regex::new(
+                r#"^(?P<type>[[:alpha:]]+)(?:\([^)]*\))?(?P<breaking>!)?:\s*(?P<summary>.+)$"#
+            ).unwrap();
+            static ref BREAKING_FOOTER: Regex = Regex::new(
+                r#"(?m)^BREAKING[ -]CHANGE:"#
+            ).unwrap();
+        }
+
+        let footer = BREAKING_FOOTER.is_match(body);
+        let caps = match CONVENTIONAL.captures(subject) {
+            Some(caps) => caps,
+            None => return Change {
+                kind: match footer {
+                    true => ChangeKind::Breaking,
+                    false => ChangeKind::Other,
+                },
+                summary: subject.to_owned(),
+            },
+        };
+        let breaking = caps.name("breaking").is_some() || footer;
+        let kind = match caps.name("type").unwrap().as_str().to_lowercase().as_str() {
+            _ if breaking => ChangeKind::Breaking,
+            "feat" => ChangeKind::Feature,
+            "fix" => ChangeKind::Fix,
+            _ => ChangeKind::Other,
+        };
+        Change {
+            kind,
+            summary: caps.name("summary").unwrap().as_str().to_owned(),
+        }
+    }
+
+    /// Get the changelog section for the default grouping.
+    fn section(&self) -> &'static str {
+        match self.kind {
+            ChangeKind::Breaking => "Breaking",
+            ChangeKind::Feature => "Added",
+            ChangeKind::Fix => "Fixed",
+            ChangeKind::Other => "Changed",
+        }
+    }
+}
+
+/// Suggest how to bump from a release, for some changes.
+///
+/// Before 1.0.0, breaking changes bump the minor version, and
+/// everything else the patch version, as cargo treats them.
+pub fn suggest_bump(changes: &[Change], since: Option<&Version>) -> Bump {
+    let kind = changes.iter()
+        .map(|change| change.kind)
+        .max()
+        .unwrap_or(ChangeKind::Other);
+    let stable = since.map(|version| version.major > 0).unwrap_or(false);
+    match (kind, stable) {
+        (ChangeKind::Breaking, true) => Bump::Major,
+        (ChangeKind::Breaking, false) => Bump::Minor,
+        (ChangeKind::Feature, true) => Bump::Minor,
+        _ => Bump::Patch,
+    }
+}
+
+/// Read the subject of each commit, and the change it makes.
+pub fn read_changes(repo: &Path, commits: &[Commit]) -> Vec<(String, Change)> {
+    commits.iter()
+        .map(|commit| {
+            let mut lines: Vec<String> = exec!(
+                [&repo, r#"git log --format="%s%n%b" -n 1 {}"#, commit.hash]
+                | (preadlns));
+            let subject = match lines.is_empty() {
+                true => String::new(),
+                false => lines.remove(0),
+            };
+            let change = Change::parse(&subject, &lines.join("\n"));
+            (subject, change)
+        })
+        .collect()
+}
+
+/// Draft a changelog entry for the commits which effect a
+/// package since its latest release, or all of them if it
+/// was never released.
+///
+/// If the config has changelog groups, each commit subject is
+/// put in the first group whose pattern matches it, or left
+/// out if none do. Otherwise, they're grouped by type.
+pub fn draft(
+    repo: &Path,
+    config: &Config,
+    package_path: &Path,
+    package: &str,
+) -> Result<Draft, Error> {
+    let (since, commits) = match find_release(repo, config, package_path, package) {
+        Ok(found) => (Some(found.version), found.unreleased),
+        Err(e) => {
+            info!("Drafting from all commits, since: {}", e);
+            (None, git::follow(repo, package_path))
+        },
+    };
+
+    let (subjects, changes): (Vec<String>, Vec<Change>) = read_changes(repo, &commits)
+        .into_iter()
+        .unzip();
+
+    let sections = group_changes(config, &subjects, &changes)?;
+    let bump = suggest_bump(&changes, since.as_ref());
+    Ok(Draft {
+        since,
+        sections,
+        bump,
+    })
+}
+
+/// Group commits into changelog sections, by the config's
+/// changelog groups, or by type if there are none.
+fn group_changes(
+    config: &Config,
+    subjects: &[String],
+    changes: &[Change],
+) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    let mut push = |section: &str, line: String| {
+        match sections.iter_mut().find(|(s, _)| s == section) {
+            Some((_, lines)) => lines.push(line),
+            None => sections.push((section.to_owned(), vec![line])),
+        };
+    };
+    if config.changelog_groups.is_empty() {
+        for change in changes {
+            push(change.section(), change.summary.clone());
+        }
+        sections.sort_by_key(|(section, _)|
+            ["Breaking", "Added", "Fixed", "Changed"].iter()
+                .position(|s| s == section));
+    } else {
+        let groups = config.changelog_groups.iter()
+            .map(|(section, pattern)| Regex::new(pattern)
+                .map(|regex| (section, regex))
+                .map_err(Error::from))
+            .collect::<Result<Vec<_>, Error>>()?;
+        for subject in subjects {
+            match groups.iter().find(|(_, regex)| regex.is_match(subject)) {
+                Some((section, _)) => push(section, subject.clone()),
+                None => debug!("Leaving out commit {:?}, which matches no \
+                    changelog group", subject),
+            };
+        }
+        sections.sort_by_key(|(section, _)|
+            groups.iter().position(|(s, _)| s == &section));
+    }
+
+    Ok(sections)
+}
+
+impl Display for Draft {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, (section, lines)) in self.sections.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            writeln!(f, "### {}", section)?;
+            for line in lines {
+                writeln!(f, "- {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn change(subject: &str, body: &str) -> (ChangeKind, String) {
+        let change = Change::parse(subject, body);
+        (change.kind, change.summary)
+    }
+    
+    fn changes(subjects: &[&str]) -> (Vec<String>, Vec<Change>) {
+        subjects.iter()
+            .map(|&subject| (subject.to_owned(), Change::parse(subject, "")))
+            .unzip()
+    }
+    
+    #[test]
+    fn parse_conventional_commits() {
+        let kind = |kind, summary: &str| (kind, summary.to_owned());
+        assert_eq!(change("feat: Add things", ""), kind(ChangeKind::Feature, "Add things"));
+        assert_eq!(change("fix(parser): Fix things", ""), kind(ChangeKind::Fix, "Fix things"));
+        assert_eq!(change("Feat: Add things", ""), kind(ChangeKind::Feature, "Add things"));
+        assert_eq!(change("docs: Explain things", ""), kind(ChangeKind::Other, "Explain things"));
+        assert_eq!(change("Update things", ""), kind(ChangeKind::Other, "Update things"));
+        
+        // breaking changes
+        assert_eq!(change("feat!: Remove things", ""), kind(ChangeKind::Breaking, "Remove things"));
+        assert_eq!(change("refactor(api)!: Rename things", ""), 
+            kind(ChangeKind::Breaking, "Rename things"));
+        assert_eq!(change("fix: Fix things", "Details.\n\nBREAKING CHANGE: they're gone"), 
+            kind(ChangeKind::Breaking, "Fix things"));
+        assert_eq!(change("Remove things", "BREAKING-CHANGE: they're gone"), 
+            kind(ChangeKind::Breaking, "Remove things"));
+        // only as a footer
+        assert_eq!(change("fix: Fix things", "Not a BREAKING CHANGE: really"), 
+            kind(ChangeKind::Fix, "Fix things"));
+    }
+    
+    #[test]
+    fn suggest_bumps() {
+        let bump = |subjects: &[&str], since: Option<&str>| suggest_bump(
+            &changes(subjects).1, 
+            since.map(|v| Version::parse(v).unwrap()).as_ref());
+        let breaking = &["fix: Fix things", "feat!: Remove things", "feat: Add things"];
+        let feature = &["fix: Fix things", "feat: Add things", "docs: Explain things"];
+        let fix = &["fix: Fix things", "docs: Explain things"];
+        
+        assert_eq!(bump(breaking, Some("1.2.0")), Bump::Major);
+        assert_eq!(bump(feature, Some("1.2.0")), Bump::Minor);
+        assert_eq!(bump(fix, Some("1.2.0")), Bump::Patch);
+        assert_eq!(bump(&[], Some("1.2.0")), Bump::Patch);
+        // before 1.0.0
+        assert_eq!(bump(breaking, Some("0.2.0")), Bump::Minor);
+        assert_eq!(bump(feature, Some("0.2.0")), Bump::Patch);
+        assert_eq!(bump(breaking, None), Bump::Minor);
+    }
+    
+    #[test]
+    fn group_by_type() {
+        let (subjects, changes) = changes(&[
+            "docs: Explain things", 
+            "fix: Fix things", 
+            "feat: Add things", 
+            "feat!: Remove things",
+            "fix: Fix more things",
+        ]);
+        let sections = group_changes(&Config::default(), &subjects, &changes).unwrap();
+        assert_eq!(sections, vec![
+            ("Breaking".to_owned(), vec!["Remove things".to_owned()]),
+            ("Added".to_owned(), vec!["Add things".to_owned()]),
+            ("Fixed".to_owned(), vec!["Fix things".to_owned(), "Fix more things".to_owned()]),
+            ("Changed".to_owned(), vec!["Explain things".to_owned()]),
+        ]);
+    }
+    
+    #[test]
+    fn group_by_first_match() {
+        let config = Config {
+            changelog_groups: vec![
+                ("Features".to_owned(), "^feat".to_owned()),
+                ("Parser".to_owned(), r"\(parser\)".to_owned()),
+                ("Fixes".to_owned(), "^fix".to_owned()),
+            ],
+            ..Config::default()
+        };
+        let (subjects, changes) = changes(&[
+            "fix(parser): Fix parsing",
+            "feat(parser): Parse more",
+            "fix: Fix things",
+            "docs: Explain things",
+        ]);
+        let sections = group_changes(&config, &subjects, &changes).unwrap();
+        assert_eq!(sections, vec![
+            ("Features".to_owned(), vec!["feat(parser): Parse more".to_owned()]),
+            ("Parser".to_owned(), vec!["fix(parser): Fix parsing".to_owned()]),
+            ("Fixes".to_owned(), vec!["fix: Fix things".to_owned()]),
+        ]);
+    }
+}
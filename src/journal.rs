@@ -0,0 +1,313 @@
+//! Journal of the steps of a release, so that a wet release
+//! which fails part way through can be resumed.
+//!
+//! The journal is a text file next to the scratch repo, with
+//! a header describing the release, followed by a line for
+//! each step as it completes.
+
+use crate::{
+    MoistMeter,
+    util::cli::ResultExt,
+    release::LocalRepo,
+    config::Config,
+};
+use std::{
+    path::{Path, PathBuf},
+    fs::{self, OpenOptions, read_to_string},
+    io::Write,
+};
+use semver::Version;
+use failure::{Error, format_err};
+
+/// File extension of journals.
+pub const JOURNAL_EXT: &str = "journal";
+
+/// Marker line for a journal whose release finished.
+const FINISHED: &str = "finished";
+
+/// Journal of a release in a scratch repo.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    /// Journal file, or none if dry.
+    path: Option<PathBuf>,
+    /// Scratch repo the release is made in.
+    pub srp: PathBuf,
+    /// Local repo the release is made from.
+    pub repo: String,
+    /// Branch to push to.
+    pub branch: String,
+    /// Remote to push to.
+    pub remote: String,
+    /// Registry to publish to, if not crates.io.
+    pub registry: Option<String>,
+    /// Packages to release, in order.
+    pub releases: Vec<JournalRelease>,
+    done: Vec<String>,
+    finished: bool,
+}
+
+/// A package release within a journal.
+#[derive(Debug, Clone)]
+pub struct JournalRelease {
+    /// Package directory, within the scratch repo.
+    pub path: PathBuf,
+    pub name: String,
+    pub version: Version,
+    /// Whether to promote the unreleased changelog entry to
+    /// this version.
+    pub promote_unreleased: bool,
+}
+
+impl Journal {
+    /// Get the journal path for a scratch repo.
+    pub fn path_for(srp: &Path) -> PathBuf {
+        srp.with_extension(JOURNAL_EXT)
+    }
+
+    /// Start a journal for releases in a scratch repo.
+    ///
+    /// When dry, the journal is only kept in memory.
+    pub fn create(
+        srp: &Path,
+        repo: &LocalRepo,
+        config: &Config,
+        registry: Option<&str>,
+        releases: Vec<JournalRelease>,
+        moist: MoistMeter,
+    ) -> Result<Self, Error> {
+        let journal = Journal {
+            path: match moist {
+                MoistMeter::Dry => None,
+                MoistMeter::Wet => Some(Journal::path_for(srp)),
+            },
+            srp: srp.to_owned(),
+            repo: repo.path.clone(),
+            branch: repo.branch.clone(),
+            remote: config.remote.clone(),
+            registry: registry.map(String::from),
+            releases,
+            done: Vec::new(),
+            finished: false,
+        };
+
+        if let Some(path) = journal.path.as_ref() {
+            let mut header = String::new();
+            header.push_str(&format!("srp = {}\n", journal.srp.display()));
+            header.push_str(&format!("repo = {}\n", journal.repo));
+            header.push_str(&format!("branch = {}\n", journal.branch));
+            header.push_str(&format!("remote = {}\n", journal.remote));
+            if let Some(registry) = journal.registry.as_ref() {
+                header.push_str(&format!("registry = {}\n", registry));
+            }
+            for release in &journal.releases {
+                header.push_str(&format!("release = {} {} {}\n",
+                    release.name, release.version, release.path.display()));
+                if release.promote_unreleased {
+                    header.push_str(&format!("promote = {}\n", release.name));
+                }
+            }
+            fs::write(path, header).map_err(Error::from)?;
+            info!("Recording release steps in journal at:\n{:?}", path);
+            info!("If the release fails part way through, finish it with:\n\
+                deet resume {}", journal.srp.display());
+        }
+
+        Ok(journal)
+    }
+
+    /// Read a journal file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = read_to_string(path).map_err(Error::from)?;
+
+        let mut srp = None;
+        let mut repo = None;
+        let mut branch = None;
+        let mut remote = None;
+        let mut registry = None;
+        let mut releases = Vec::new();
+        let mut done = Vec::new();
+        let mut finished = false;
+        for line in data.lines().filter(|line| !line.trim().is_empty()) {
+            if line == FINISHED {
+                finished = true;
+                continue;
+            }
+            let (key, value) = line.split_once(" = ")
+                .ok_or_else(|| format_err!("invalid journal line {:?} \
+                    at:\n{:?}", line, path))?;
+            match key {
+                "srp" => srp = Some(PathBuf::from(value)),
+                "repo" => repo = Some(value.to_owned()),
+                "branch" => branch = Some(value.to_owned()),
+                "remote" => remote = Some(value.to_owned()),
+                "registry" => registry = Some(value.to_owned()),
+                "release" => {
+                    let mut parts = value.splitn(3, ' ');
+                    let (name, version, package) = match (
+                        parts.next(), parts.next(), parts.next())
+                    {
+                        (Some(n), Some(v), Some(p)) => (n, v, p),
+                        _ => return Err(format_err!("invalid journal release \
+                            {:?} at:\n{:?}", value, path)),
+                    };
+                    releases.push(JournalRelease {
+                        path: PathBuf::from(package),
+                        name: name.to_owned(),
+                        version: Version::parse(version).map_err(Error::from)?,
+                        promote_unreleased: false,
+                    });
+                },
+                "promote" => {
+                    releases.iter_mut()
+                        .find(|release| release.name == value)
+                        .ok_or_else(|| format_err!("journal promotes unknown \
+                            release {:?} at:\n{:?}", value, path))?
+                        .promote_unreleased = true;
+                },
+                "done" => done.push(value.to_owned()),
+                _ => return Err(format_err!("unknown journal key {:?} \
+                    at:\n{:?}", key, path)),
+            };
+        }
+
+        let missing = |key: &str| format_err!("journal is missing {} \
+            at:\n{:?}", key, path);
+        Ok(Journal {
+            path: Some(path.to_owned()),
+            srp: srp.ok_or_else(|| missing("srp"))?,
+            repo: repo.ok_or_else(|| missing("repo"))?,
+            branch: branch.ok_or_else(|| missing("branch"))?,
+            remote: remote.ok_or_else(|| missing("remote"))?,
+            registry,
+            releases,
+            done,
+            finished,
+        })
+    }
+
+    /// Read all journals in a temp directory.
+    pub fn list<P: AsRef<Path>>(tmp: P) -> Result<Vec<Self>, Error> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(tmp.as_ref()).map_err(Error::from)? {
+            let path = entry.map_err(Error::from)?.path();
+            if path.extension().map(|ext| ext == JOURNAL_EXT).unwrap_or(false) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.into_iter().map(Journal::open).collect()
+    }
+
+    /// Get the journal file, if not dry.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Get the steps completed so far.
+    pub fn done(&self) -> &[String] {
+        &self.done
+    }
+
+    /// Whether a step was completed.
+    pub fn is_done(&self, step: &str) -> bool {
+        self.done.iter().any(|s| s == step)
+    }
+
+    /// Whether every step was completed.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Run a step, unless it was already completed, then
+    /// record it as completed.
+    pub fn step<F: FnOnce()>(&mut self, step: &str, func: F) {
+        if self.is_done(step) {
+            debug!("Skipping step {:?}, which was already done", step);
+            return;
+        }
+        func();
+        self.append(&format!("done = {}", step)).ekill();
+        self.done.push(step.to_owned());
+    }
+
+    /// Record that every step was completed.
+    pub fn finish(&mut self) {
+        self.append(FINISHED).ekill();
+        self.finished = true;
+    }
+
+    fn append(&self, line: &str) -> Result<(), Error> {
+        if let Some(path) = self.path.as_ref() {
+            let mut file = OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(Error::from)?;
+            writeln!(file, "{}", line).map_err(Error::from)?;
+            file.sync_data().map_err(Error::from)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestDir;
+    
+    #[test]
+    fn journal_round_trip() {
+        let dir = TestDir::new("journal");
+        let srp = dir.path().join("srp-test");
+        let repo = LocalRepo {
+            path: "/repo".to_owned(),
+            branch: "main".to_owned(),
+        };
+        let releases = vec![
+            JournalRelease {
+                path: srp.join("a"),
+                name: "a".to_owned(),
+                version: Version::parse("0.1.0").unwrap(),
+                promote_unreleased: false,
+            },
+            JournalRelease {
+                path: srp.join("b"),
+                name: "b".to_owned(),
+                version: Version::parse("1.0.0-rc.1").unwrap(),
+                promote_unreleased: true,
+            },
+        ];
+        let mut journal = Journal::create(
+            &srp, &repo, &Config::default(), Some("local"), releases, MoistMeter::Wet,
+        ).unwrap();
+        let mut ran = Vec::new();
+        journal.step("commit a-v0.1.0", || ran.push("commit"));
+        journal.step("tag a-v0.1.0", || ran.push("tag"));
+        journal.step("commit a-v0.1.0", || ran.push("commit again"));
+        assert_eq!(ran, vec!["commit", "tag"]);
+        
+        let mut opened = Journal::open(Journal::path_for(&srp)).unwrap();
+        assert_eq!(opened.srp, srp);
+        assert_eq!(opened.repo, "/repo");
+        assert_eq!(opened.branch, "main");
+        assert_eq!(opened.remote, "origin");
+        assert_eq!(opened.registry.as_deref(), Some("local"));
+        let releases: Vec<(String, String, PathBuf, bool)> = opened.releases.iter()
+            .map(|r| (r.name.clone(), r.version.to_string(), r.path.clone(), r.promote_unreleased))
+            .collect();
+        assert_eq!(releases, vec![
+            ("a".to_owned(), "0.1.0".to_owned(), srp.join("a"), false),
+            ("b".to_owned(), "1.0.0-rc.1".to_owned(), srp.join("b"), true),
+        ]);
+        assert_eq!(opened.done(), ["commit a-v0.1.0", "tag a-v0.1.0"]);
+        assert!(!opened.is_finished());
+        
+        // resuming skips what was done, and records the rest
+        opened.step("tag a-v0.1.0", || panic!("step ran twice"));
+        opened.step("publish a-v0.1.0", || ());
+        opened.finish();
+        let finished = Journal::open(Journal::path_for(&srp)).unwrap();
+        assert_eq!(finished.done().len(), 3);
+        assert!(finished.is_finished());
+    }
+}
@@ -43,7 +43,7 @@ macro_rules! color {
     (
         @fstr($fstr:expr) , $($arg:tt)*
     )=>{
-        println!($fstr, $($arg)*)
+        $crate::leet::print_line(format_args!($fstr, $($arg)*))
     };
     // base case
     (
@@ -61,7 +61,7 @@ impl Display for Problem {
         f.write_fmt(format_args!(
             "{}[{}:{}] {}", 
             self.level, 
-            self.record.file.as_ref().map(String::as_str).unwrap_or("?"),
+            self.record.file.as_deref().unwrap_or("?"),
             self.record.line.map(|n| n.to_string()).unwrap_or("?".to_string()),
             self.record.body))?;
         
@@ -135,6 +135,7 @@ impl Display for PreFormatted {
 }
 
 impl<'a> From<&'a Arguments<'a>> for PreFormatted {
+    #[allow(clippy::unused_format_specs)]
     fn from(args: &'a Arguments<'a>) -> PreFormatted {
         PreFormatted {
             display: format!("{}", args),
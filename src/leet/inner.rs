@@ -9,8 +9,12 @@ use super::{
     indent::{Indent, IndentDisplay},
 };
 use std::{
-    io::{stdout, Write},
-    sync::Mutex,
+    io::{stdout, stderr, Write},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    fmt,
     ops::Deref,
     backtrace::Backtrace,
     sync::Arc,
@@ -21,6 +25,19 @@ use lazy_static::lazy_static;
 lazy_static! {
     pub static ref INDENT: Mutex<u32> = Mutex::new(0);
     pub static ref CATCH: Mutex<Vec<Vec<Problem>>> = Mutex::new(Vec::new());
+    pub static ref FAILURE_NOTES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Whether log output goes to stderr rather than stdout.
+pub static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Print a line of log output.
+pub fn print_line(args: fmt::Arguments) {
+    if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", args);
+    } else {
+        println!("{}", args);
+    }
 }
 
 #[allow(dead_code)]
@@ -78,16 +95,15 @@ impl Log for Logger {
             Level::Debug => color!(blue   "[ INFO  ]";str),
         };
         
-        let forigin: String;
-        if self.mode == LogMode::Trace {
-            forigin = format!("[{}:{}]",
+        let forigin: String = if self.mode == LogMode::Trace {
+            format!("[{}:{}]",
                 record.module_path().unwrap_or("?"),
                 record.line()
                     .map(|n| format!("{}", n))
-                    .unwrap_or(format!("?")));
+                    .unwrap_or_else(|| "?".to_owned()))
         } else {
-            forigin = format!("");
-        }
+            String::new()
+        };
         let forigin: String = match record.level() {
             Level::Info =>  color!(yellow "{}";format, forigin),
             Level::Warn =>  color!(red    "{}";format, forigin),
@@ -112,7 +128,7 @@ impl Log for Logger {
             },
             body: format!("{}{} {}", fstatus, forigin, record.args()),
         };
-        println!("{}", display);
+        print_line(format_args!("{}", display));
         
         if record.level() <= Level::Warn {
             let mut guard = CATCH.lock().unwrap();
@@ -136,5 +152,6 @@ impl Log for Logger {
     
     fn flush(&self) {
         stdout().flush().expect("failed to flush stdout");
+        stderr().flush().expect("failed to flush stderr");
     }
 }
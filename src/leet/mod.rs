@@ -15,14 +15,18 @@ mod scope;
 mod indent;
 
 /// Implementation guts.
-pub (self) mod inner;
+mod inner;
 
 pub use scope::{
     LogIndent, log_indent,
     CatchErrors, catch_errors,
+    on_failure, exit_failure,
 };
 
+pub use inner::print_line;
+
 use log::LevelFilter;
+use std::sync::atomic::Ordering;
 
 
 /// Create and install a LEET logger.
@@ -35,7 +39,7 @@ pub fn init(mode: LogMode) {
 /// variable.
 pub fn init_from_env() {
     let var0: Option<String> = std::env::var("LOG").ok();
-    let var1: Option<&str> = var0.as_ref().map(String::as_str);
+    let var1: Option<&str> = var0.as_deref();
     match var1 {
         None | Some("default") => {
             init(LogMode::Default);
@@ -49,6 +53,12 @@ pub fn init_from_env() {
     };
 }
 
+/// Send log output to stderr rather than stdout, so
+/// that stdout is left for machine-readable output.
+pub fn log_to_stderr() {
+    inner::TO_STDERR.store(true, Ordering::Relaxed);
+}
+
 /// LEET logger.
 #[derive(Clone, Default)]
 pub struct Logger {
@@ -56,8 +66,9 @@ pub struct Logger {
 }
 
 /// LEET logger verbosity level.
-#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub enum LogMode {
+    #[default]
     Default,
     Verbose,
     Trace,
@@ -94,9 +105,3 @@ impl LogMode {
         }
     }
 }
-
-impl Default for LogMode {
-    fn default() -> LogMode {
-        LogMode::Default
-    }
-}
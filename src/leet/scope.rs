@@ -1,7 +1,7 @@
 
 use super::{
     error::{Problem, ProblemLevel},
-    inner::{INDENT, CATCH, m_edit, m_edit_read},
+    inner::{INDENT, CATCH, FAILURE_NOTES, m_edit, m_edit_read, print_line},
 };
 use std::{
     process,
@@ -36,12 +36,28 @@ impl LogIndent {
     /// of a logged operation.
     pub fn linebreak(&self) {
         if m_edit_read(&&self.linebreaks, |&l| (l + 1, l > 0)) {
-            println!();
+            print_line(format_args!(""));
         }
     }
 }
 
 
+/// Log a note if the process later exits in failure.
+pub fn on_failure(note: String) {
+    FAILURE_NOTES.lock().unwrap().push(note);
+}
+
+/// Log the notes registered with `on_failure`, then exit
+/// the process in failure.
+pub fn exit_failure() -> ! {
+    let notes = FAILURE_NOTES.lock().unwrap().drain(..).collect::<Vec<String>>();
+    for note in notes {
+        info!("{}", note);
+    }
+    color!("\n";red "[ EXIT  ] Process failed.";"\n";,);
+    process::exit(1)
+}
+
 /// Catch errors on the global scope for the lifetime of this guard.
 pub struct CatchErrors {
     _indent: Option<LogIndent>,
@@ -71,8 +87,7 @@ impl CatchErrors {
     pub fn get(mut self) -> Vec<Problem> {
         self.handled = true;
         let mut guard = CATCH.lock().unwrap();
-        let vec = guard.pop().unwrap();
-        vec
+        guard.pop().unwrap()
     }
     
     /// If any non-pardoned errors occured, exit the 
@@ -82,12 +97,24 @@ impl CatchErrors {
         if pardon_warnings {
             problems.retain(|p| p.level() == ProblemLevel::Error);
         }
-        if problems.len() > 0 {
-            color!("\n";red "[ EXIT  ] Process failed.";"\n";,);
-            process::exit(1);
+        if !problems.is_empty() {
+            exit_failure();
         }
     }
     
+    /// If any non-pardoned errors occured so far, exit
+    /// the process, but otherwise keep catching.
+    pub fn checkpoint(&self, pardon_warnings: bool) {
+        let failed = CATCH.lock().unwrap()
+            .last()
+            .map(|problems| problems.iter()
+                .any(|p| !pardon_warnings || p.level() == ProblemLevel::Error))
+            .unwrap_or(false);
+        if failed {
+            exit_failure();
+        }
+    }
+
     /// Put an empty line between repetetions 
     /// of a logged operation.
     ///
@@ -9,58 +9,108 @@ pub mod leet;
 pub mod maniflect;
 /// Changelog parsing.
 pub mod changelog;
+pub mod config;
+pub mod bump;
+pub mod release;
+pub mod journal;
+pub mod plan;
+pub mod scratch;
+pub mod draft;
 
 use crate::{
     util::{
-        hex::Hex,
         cli::{
-            parse_var,
             ResultExt,
+            take_flag,
+            take_opt,
         },
-        display::{
-            Lines, 
-            LinesView,
-        },
-        cmd::{
-            preadln, 
-            preadlns,
-            pnonempty,
-        },
+        display::{Lines, Age},
         path::path_rebase,
+        cmd::preadlns,
         git,
     },
-    maniflect::{ManifestFile, DepSource},
+    maniflect::{ManifestFile, Workspace},
     leet::{
         catch_errors,
         log_indent,
     },
-    changelog::read_changelog,
-};
-use std::{
-    path::{PathBuf, Path},
-    fs::{
-        self,
-        canonicalize,
-        create_dir as mkdir,
+    changelog::insert_unreleased,
+    config::{Config, DevDepPolicy},
+    bump::VersionSpec,
+    release::{
+        inspect_repo,
+        create_scratch_repo,
+        apply_delocalize,
+        run_checks,
+        lint_changelog,
+        lint_changelog_stage,
+        prepare_release,
+        execute_releases,
+        plan_changed_release,
+    },
+    scratch::{
+        tmp_dir,
+        list_scratch_repos,
+        remove_scratch_repo,
+        cleanup_scratch_repo,
     },
+    journal::{Journal, JournalRelease},
+    plan::{Plan, PlanFormat},
+    draft::draft,
 };
-use rand::prelude::*;
-use semver::{
-    Version, 
-    VersionReq
+use std::{
+    path::PathBuf,
+    fs::canonicalize,
+    time::Duration,
 };
 
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-enum MoistMeter {
+pub enum MoistMeter {
     Dry,
     Wet,
 }
 
+/// Options which may be passed to any subcommand.
+#[derive(Debug, Clone)]
+struct Options {
+    /// Registry to publish to, instead of crates.io.
+    registry: Option<String>,
+    /// Output format, for subcommands with machine-readable
+    /// output.
+    format: PlanFormat,
+    /// Keep the scratch repo even if the process succeeds.
+    keep_scratch: bool,
+    /// Release changed local dependencies before the package.
+    release_deps: bool,
+    /// Allow releasing versions lower than the newest release,
+    /// for maintenance lines.
+    allow_lower: bool,
+    /// Directory to write release notes into.
+    notes_dir: Option<PathBuf>,
+}
+
+impl Options {
+    /// Remove the options from program args.
+    fn take(args: &mut Vec<String>) -> Self {
+        Options {
+            registry: take_opt(args, "--registry").ekill(),
+            format: take_opt(args, "--format").ekill()
+                .map(|format| format.parse().ekill())
+                .unwrap_or(PlanFormat::Text),
+            keep_scratch: take_flag(args, "--keep-scratch"),
+            release_deps: take_flag(args, "--release-deps"),
+            allow_lower: take_flag(args, "--allow-lower"),
+            notes_dir: take_opt(args, "--notes-dir").ekill().map(PathBuf::from),
+        }
+    }
+}
+
 /// Check subcommand.
 fn run<P: AsRef<str>>(
     package: P,
-    version: Option<Version>,
+    version: Option<VersionSpec>,
     moist: MoistMeter,
+    options: &Options,
 ) {
     match moist {
         MoistMeter::Dry => info!("Executing DEET check"),
@@ -72,280 +122,472 @@ fn run<P: AsRef<str>>(
     let pckg = canonicalize(&pckg).ekill();
     debug!("For package at:\n{:?}", pckg);
     
-    let pckg_repo = exec!(
-        [&pckg, "git rev-parse --show-toplevel"] 
-        | (preadln)
-    );
-    debug!("Using the repo at:\n{:?}", pckg_repo);
-    
-    let pckg_branch = exec!(
-        [&pckg, "git rev-parse --abbrev-ref HEAD"]
-        | (preadln)
-    );
-    debug!("Which is in branch {:?}", pckg_branch);
-    if pckg_branch != "main" {
-        match moist {
-            MoistMeter::Dry => warn!("Repo is not in main branch"),
-            MoistMeter::Wet => kill!("Repo is not in main branch"),
-        };
+    if options.release_deps && version.is_none() {
+        kill!("--release-deps needs a version to release");
     }
-    if exec!(
-        [&pckg_repo, "git log origin/{}..HEAD", pckg_branch] | (pnonempty)
-    ) {
-        match moist {
-            MoistMeter::Dry => warn!("Repo has unpushed commits"),
-            MoistMeter::Wet => kill!("Repo has unpushed commits"),
-        };
+    let plan = Plan::build(
+        &pckg, version, moist, options.registry.as_deref(), 
+        options.release_deps, options.allow_lower);
+    
+    // when releasing dependencies first, each package is
+    // delocalized and checked right before its release
+    let prepared = plan.dep_releases.is_empty();
+    if prepared {
+        // ==== de-localize paths ====
+        
+        apply_delocalize(&plan.package_path, &plan.deps);
+        
+        // run checks
+        run_checks(&plan.package_path, &plan.config);
+        lint_changelog_stage(&plan.srp, &plan.config, &plan.package_path);
+    } else {
+        info!("Releasing, in order:\n{}", Lines(plan.dep_releases.iter()
+            .map(|r| format!("{} {}", r.name, r.version))
+            .chain(plan.version.iter()
+                .map(|v| format!("{} {}", plan.package_name, v)))));
     }
-    if exec!(
-        [&pckg_repo, "git log HEAD..origin/{}", pckg_branch] | (pnonempty)
-    ) {
-        match moist {
-            MoistMeter::Dry => warn!("Repo is behind origin"),
-            MoistMeter::Wet => kill!("Repo is behind origin"),
-        };
+    
+    let version = match plan.version.clone() {
+        None => {
+            info!("Since no version to release was specified, the check is ending now.");
+            catch.handle(true);
+            cleanup_scratch_repo(&plan.srp, options.keep_scratch);
+            return;
+        },
+        Some(v) => v,
+    };
+    
+    let mut releases = plan.dep_releases.clone();
+    releases.push(JournalRelease {
+        path: plan.package_path.clone(),
+        name: plan.package_name.clone(),
+        version,
+        promote_unreleased: plan.promote_unreleased,
+    });
+    if moist == MoistMeter::Wet {
+        catch.checkpoint(false);
     }
-    let tmp: PathBuf = parse_var("DEET_TMP_DIR").ekill();
-    let tmp = canonicalize(&tmp).ekill();
-    debug!("Using temp directory:\n{:?}", &tmp);
-    let srp: PathBuf = tmp.join(format!("srp-{}", random::<Hex>()));
-    debug!("Creating scratch repo in:\n{:?}", srp);
-    
-    mkdir(&srp).ekill();
-    exec!([&srp, "git init"]);
+    let mut journal = Journal::create(
+        &plan.srp, &plan.repo, &plan.config, options.registry.as_deref(),
+        releases, moist,
+    ).ekill();
+    
+    let notes_dir = options.notes_dir.as_deref();
+    execute_releases(&mut journal, &catch, moist, notes_dir, |release, released| match prepared {
+        // already delocalized and checked
+        true => false,
+        false => prepare_release(
+            &plan.srp, release, released, options.registry.as_deref(), moist),
+    });
+    catch.handle(false);
+    cleanup_scratch_repo(&plan.srp, options.keep_scratch);
+    
+    color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
+}
+
+/// A workspace member to be released.
+struct MemberRelease {
+    release: JournalRelease,
+    config: Config,
+}
+
+/// Workspace check subcommand.
+///
+/// Releases every member which changed since its last 
+/// release, in dependency order, within one scratch repo.
+fn run_workspace<P: AsRef<str>>(
+    workspace: P,
+    moist: MoistMeter,
+    options: &Options,
+) {
     match moist {
-        MoistMeter::Dry => {
-            // pull from local, and move over local changes
-            exec!([&srp, "git remote add local {:?}", pckg_repo]);
-            exec!([&srp, "git fetch local"]);
-            exec!([&srp, "git -c advice.detachedHead=false checkout local/{}", pckg_branch]);
-            
-            let mut local_changes = false;
-            if exec!([&pckg_repo, "git diff"] | (pnonempty)) {
-                exec!([&pckg_repo, "git diff"] | [&srp, "git apply"]);
-                local_changes = true;
-            }
-            for path in exec!(
-                [&pckg_repo, "git ls-files --others --exclude-standard"] 
-                | (preadlns)) 
-            {
-                fs::create_dir_all(srp.join(&path).parent().unwrap()).ekill();
-                fs::copy(
-                    Path::new(&pckg_repo).join(&path), 
-                    srp.join(&path)
-                ).ekill();
-                local_changes = true;
-            }
-            if local_changes {
-                warn!("Uncommitted local changes copied over.");
-                if version.is_some() {
-                    trace!("Creating commit for copied over local changes");
-                    exec!([&srp, "git add ."]);
-                    exec!([&srp, r#"git commit -m "(local changes copied over by DEET)""#])
-                }
-            }
-        },
-        MoistMeter::Wet => {
-            // stopgap
-            if exec!(
-                [&pckg_repo, "git diff"] | (pnonempty)
-            ) {
-                kill!("There are uncommitted local changes:\n{}",
-                    Lines(exec!([&pckg_repo, "git diff"] | (preadlns))));
-            }
-            
-            if exec!(
-                [&pckg_repo, "git ls-files --others --exclude-standard"] | (pnonempty)
-            ) {
-                kill!("There are uncommitted local new files:\n{}",
-                    Lines(exec!([&pckg_repo, "git ls-files --others --exclude-standard"] | (preadlns))));
-            }
-            
-            let origin = exec!([&pckg_repo, "git config --get remote.origin.url"] | (preadln));
-            info!("Pulling from {}", origin);
-            exec!([&srp, "git remote add origin {:?}", origin]);
-            exec!([&srp, "git fetch origin"]);
-            exec!([&srp, "git checkout origin/{}", pckg_branch]);
-        },
+        MoistMeter::Dry => info!("Executing DEET workspace check"),
+        MoistMeter::Wet => info!("Publishing workspace via DEET"),
     };
+    let catch = catch_errors(false);
     
+    let pckg = PathBuf::from(workspace.as_ref());
+    let pckg = canonicalize(&pckg).ekill();
+    debug!("For workspace at:\n{:?}", pckg);
     
-    // ==== de-localize paths ====
+    let repo_config = Config::load(git::toplevel(&pckg), None).ekill();
+    let repo = inspect_repo(&pckg, &repo_config, moist);
+    let srp = create_scratch_repo(&repo, &repo_config, moist, true);
     
-    let package_path = path_rebase(&pckg, &pckg_repo, &srp)
+    let workspace_path = path_rebase(&pckg, &repo.path, &srp)
         .ekill();
-    let manifest_path = package_path.join("Cargo.toml");
-    info!("Delocalizing manifest at:\n{:?}", manifest_path);
-
+    let workspace = Workspace::open(&workspace_path).ekill();
+    let order = workspace.release_order(|manifest_file| 
+        Config::load(&srp, Some(manifest_file))
+            .map(|config| config.dev_deps == DevDepPolicy::Drop))
+        .ekill();
+    
+    // ==== decide what to release ====
+    
+    info!("Looking for changed workspace members");
     let indent = log_indent();
-    let mut manifest_file = ManifestFile::new(&manifest_path).ekill();
-    for mut dep in manifest_file.deps().ekill() {
-        // get and canonicalize the local path
-        let local_path = match dep.source().local_path().map(Path::new) {
-            Some(path) => {
-                if path.is_relative() {
-                    canonicalize(package_path.join(&path)).ekill()
-                } else {
-                    canonicalize(path).ekill()
-                }
-            },
-            None => continue,
-        };
-
-        indent.linebreak();
-        info!("De-localizing dependency {:?} at:\n{:?}", dep.package(), local_path);
-        
-        // list relevant commits
-        let commits = git::follow(&srp, &local_path);
-        
-        debug!("Found relevant commits:\n{}", 
-            LinesView(&commits, |c| &c.pretty));
+    let mut releases: Vec<MemberRelease> = Vec::new();
+    for member in order {
+        let manifest_file = ManifestFile::new(member.join("Cargo.toml"))
+            .ekill();
+        let package_name = manifest_file.name().ekill();
+        let config = Config::load(&srp, Some(&manifest_file)).ekill();
         
-        let latest_commit = commits.get(0)
-            .unwrap_or_else(|| kill!(
-                "You silly goose!\nThis repo doesn't have any commits"));
-        let tags: Vec<String> = exec!(
-            [&srp, "git tag --points-at {}", latest_commit.hash]
-            | (preadlns));
-        
-        info!("Looking at latest commit: {}", latest_commit.concise);
-        debug!("Found tags on commit:\n{}", Lines(&tags));
-        
-        let versions: Vec<Version> = tags.iter()
-            .filter_map(|tag| 
-                parse_release_tag(tag, dep.package()))
-            .collect();
-        
-        // select the version
-        let version = match versions.as_slice() {
-            &[] => { 
-                error!("No versions found on commit"); 
-                continue;
-            },
-            &[ref v] => v.clone(),
-            _ => { 
-                error!("Several versions found on commit:\n{}", Lines(&versions)); 
-                continue;
-            },
-        };
-        
-        info!("Found version {}", version);
+        indent.linebreak();
+        info!("Member {:?} at:\n{:?}", package_name, member);
         
-        let version_req = format!(
-            "{}", VersionReq::parse(&format!(
-                "^{}", version)).ekill());
-                
-        debug!("Replacing local dep with version req {}", version_req);
+        if !manifest_file.publishable() {
+            info!("Skipping, since it is not publishable");
+            continue;
+        }
         
-        dep.set_source(DepSource::Crates {
-            version: version_req,
-        });
+        let release = plan_changed_release(
+            &srp, &config, &manifest_file, &member, 
+            options.registry.as_deref(), options.allow_lower);
+        if let Some(release) = release {
+            releases.push(MemberRelease {
+                release,
+                config,
+            });
+        }
     }
     indent.end();
-    manifest_file.save().ekill();
     
-    // run checks
-    info!("Running cargo check");
-    exec!([&package_path, "cargo check --color always"]);
+    if releases.is_empty() {
+        catch.checkpoint(false);
+        info!("No workspace members have changed, so there is nothing to release.");
+        catch.handle(false);
+        cleanup_scratch_repo(&srp, options.keep_scratch);
+        color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
+        return;
+    }
+    info!("Releasing, in order:\n{}", Lines(releases.iter()
+        .map(|r| r.config.release_tag(&r.release.name, &r.release.version))));
+    if moist == MoistMeter::Wet {
+        catch.checkpoint(false);
+    }
+    
+    // ==== release each member ====
+    
+    let mut journal = Journal::create(
+        &srp, &repo, &repo_config, options.registry.as_deref(),
+        releases.into_iter()
+            .map(|r| r.release)
+            .collect(),
+        moist,
+    ).ekill();
+    
+    let notes_dir = options.notes_dir.as_deref();
+    execute_releases(&mut journal, &catch, moist, notes_dir, |release, released| 
+        prepare_release(
+            &srp, release, released, options.registry.as_deref(), moist));
+    catch.handle(false);
+    cleanup_scratch_repo(&srp, options.keep_scratch);
     
-    info!("Running cargo test");
-    exec!([&package_path, "cargo test --color always"]);
+    color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
+}
 
-    info!("Running cargo doc");
-    exec!([&package_path, "cargo doc --no-deps --document-private-items --color always"]);
+/// Plan subcommand.
+///
+/// Decides everything a publish would do, and prints it
+/// without changing anything.
+fn plan<P: AsRef<str>>(
+    package: P,
+    version: VersionSpec,
+    options: &Options,
+) {
+    if options.format == PlanFormat::Json {
+        leet::log_to_stderr();
+    }
+    info!("Planning DEET publish");
+    let catch = catch_errors(false);
     
-    let changelog_path = package_path.join("CHANGELOG.md");
-    info!("Reading changelog at {:?}", changelog_path);
-    let changelog = read_changelog(&changelog_path)
-        .map_err(|e| kill!("error reading changelog:\n{}", e))
-        .unwrap();
-    debug!("Changelog: \n\n{}", Lines(&changelog));
+    let pckg = PathBuf::from(package.as_ref());
+    let pckg = canonicalize(&pckg).ekill();
+    debug!("For package at:\n{:?}", pckg);
     
-    let version = match version {
-        None => {
-            info!("Since no version to release was specified, the check is ending now.");
-            return catch.handle(true);
-        },
-        Some(v) => v,
+    let plan = Plan::build(
+        &pckg, Some(version), MoistMeter::Wet, options.registry.as_deref(),
+        options.release_deps, options.allow_lower);
+    match options.format {
+        PlanFormat::Text => info!("Plan:\n{}", plan),
+        PlanFormat::Json => println!("{}", plan.to_json()),
     };
+    catch.handle(true);
+    cleanup_scratch_repo(&plan.srp, options.keep_scratch);
+}
+
+/// Resume subcommand.
+///
+/// Finishes a wet release from its journal, skipping the
+/// steps which were already done.
+fn resume(scratch: Option<&str>, options: &Options) {
+    info!("Resuming DEET release");
+    let catch = catch_errors(false);
     
-    let version_note = changelog
-        .iter()
-        .find(|e| e.version == version)
-        .cloned();
-    let version_note = match version_note {
-        Some(n) => n,
+    let journal_path = match scratch {
+        Some(scratch) => Journal::path_for(&canonicalize(scratch).ekill()),
         None => {
-            kill!("Could not find version {} in changelog", version);
+            let unfinished = Journal::list(tmp_dir()).ekill()
+                .into_iter()
+                .filter(|journal| !journal.is_finished())
+                .collect::<Vec<Journal>>();
+            match unfinished.as_slice() {
+                [journal] => journal.path().unwrap().to_owned(),
+                [] => kill!("There are no unfinished releases to resume"),
+                _ => kill!("There are several unfinished releases, pass \
+                    the scratch repo of one to resume:\n{}", 
+                    Lines(unfinished.iter().map(|j| j.srp.display()))),
+            }
         },
     };
+    let mut journal = Journal::open(&journal_path).ekill();
+    debug!("From journal at:\n{:?}", journal_path);
+    
+    if journal.is_finished() {
+        info!("Release in {:?} already finished, so there is nothing to resume.", 
+            journal.srp);
+        catch.handle(false);
+        color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
+        return;
+    }
+    if !journal.done().is_empty() {
+        info!("Steps already done:\n{}", Lines(journal.done()));
+    }
+    
+    let srp = journal.srp.clone();
+    let registry = journal.registry.clone();
+    let notes_dir = options.notes_dir.as_deref();
+    execute_releases(&mut journal, &catch, MoistMeter::Wet, notes_dir, |release, released| 
+        prepare_release(
+            &srp, release, released, registry.as_deref(), MoistMeter::Wet));
+    catch.handle(false);
+    cleanup_scratch_repo(&srp, options.keep_scratch);
+    
+    color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
+}
 
-    let package_name = manifest_file.name().ekill();
-    info!("Package name = {}", package_name);
-
-    info!("Current version = {}", manifest_file.version().ekill());
-    info!("Found version {} in changelog:\n{}", version, version_note);
-    
-    debug!("Altering version in manifest at:\n{:?}", manifest_path);
-    manifest_file.set_version(&version.to_string()).ekill();
-    manifest_file.save().ekill();
-    
-    // make a new commit
-    let publish_tag = format!("{}-v{}", package_name, version);
-    info!("Creating new commit and tagging {}", publish_tag);
-    exec!([&srp, "git add {:?}", manifest_path]);
-    exec!([&srp, r#"git commit -m "Publish {}""#, publish_tag]);
-    exec!([&srp, "git tag {} HEAD", publish_tag]);
+/// Status subcommand.
+///
+/// Lists wet releases which did not finish.
+fn status() {
+    let tmp = tmp_dir();
+    info!("Looking for unfinished releases in {:?}", tmp);
+    
+    let unfinished = Journal::list(&tmp).ekill()
+        .into_iter()
+        .filter(|journal| !journal.is_finished())
+        .collect::<Vec<Journal>>();
+    if unfinished.is_empty() {
+        info!("There are no unfinished releases.");
+        return;
+    }
+    for journal in &unfinished {
+        warn!("Unfinished release in scratch repo:\n{:?}", journal.srp);
+        let indent = log_indent();
+        info!("Releasing:\n{}", Lines(journal.releases.iter()
+            .map(|r| format!("{} {}", r.name, r.version))));
+        if journal.done().is_empty() {
+            info!("No steps done yet");
+        } else {
+            info!("Steps done:\n{}", Lines(journal.done()));
+        }
+        indent.end();
+    }
+    info!("Finish a release with `deet resume [scratch repo]`");
+}
 
-    match moist {
-        MoistMeter::Dry => {
-            info!("Running cargo publish dry run");
-            exec!([package_path, "cargo publish  --color always --locked --dry-run --allow-dirty"]);
+/// Changelog lint subcommand.
+///
+/// Reports problems with a package's changelog, compared with
+/// its release tags in the local repo.
+fn changelog_lint<P: AsRef<str>>(package: P) {
+    info!("Linting changelog via DEET");
+    let catch = catch_errors(false);
+    
+    let pckg = PathBuf::from(package.as_ref());
+    let pckg = canonicalize(&pckg).ekill();
+    let repo = git::toplevel(&pckg);
+    let manifest_file = ManifestFile::new(pckg.join("Cargo.toml")).ekill();
+    let config = Config::load(&repo, Some(&manifest_file)).ekill();
+    
+    match lint_changelog(repo.as_ref(), &config, &pckg) {
+        0 => info!("Found no problems with the changelog"),
+        n => info!("Found {} problems with the changelog", n),
+    };
+    catch.handle(false);
+    
+    color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
+}
 
-            catch.handle(false);
-        },
-        MoistMeter::Wet => {
-            catch.handle(false);
-            
-            info!("Publishing to crates.io");
-            exec!([package_path, "cargo publish --color always --locked"]);
-            
-            color!(green "[ INFO  ] Successfully published, committing and pushing.";,);
-            manifest_file.set_version(&format!("{}-AFTER", version)).ekill();
-            exec!([&srp, "git add {:?}", manifest_path]);
-            exec!([&srp, r#"git commit -m "After-release {}""#, publish_tag]);
-            exec!([&srp, "git checkout -b {}", pckg_branch]);
-            exec!([&srp, "git push -u origin {0}:{0}", pckg_branch]);
-            exec!([&srp, "git push -u origin {0}:{0}", publish_tag]);
-            exec!([&pckg_repo, "git fetch origin"]);
-            exec!([&pckg_repo, "git pull origin {}", pckg_branch]);
-            exec!([&pckg_repo, "git pull origin {}", publish_tag]);
-        }
+/// Changelog draft subcommand.
+///
+/// Drafts a changelog entry from the commits which effect a 
+/// package since its latest release in the local repo, and 
+/// suggests a version bump. If `insert`, the draft is put in
+/// the unreleased entry of the changelog.
+fn changelog_draft<P: AsRef<str>>(package: P, insert: bool) {
+    info!("Drafting changelog via DEET");
+    
+    let pckg = PathBuf::from(package.as_ref());
+    let pckg = canonicalize(&pckg).ekill();
+    let repo = git::toplevel(&pckg);
+    let manifest_file = ManifestFile::new(pckg.join("Cargo.toml")).ekill();
+    let config = Config::load(&repo, Some(&manifest_file)).ekill();
+    let name = manifest_file.name().ekill();
+    
+    let draft = draft(repo.as_ref(), &config, &pckg, &name).ekill();
+    if draft.sections.is_empty() {
+        kill!("Found no changes to {} to draft", name);
+    }
+    info!("Draft:\n{}", draft);
+    match draft.since.as_ref() {
+        Some(since) => info!("Suggested bump from {}: {} (to {})", 
+            since, draft.bump, draft.bump.apply(since)),
+        None => info!("Suggested bump: {}", draft.bump),
     };
     
+    if insert {
+        let changelog_path = config.changelog_path(repo.as_ref(), &pckg, &name);
+        info!("Inserting draft into unreleased changes at {:?}", changelog_path);
+        insert_unreleased(&changelog_path, config.changelog_section(&name), &draft.to_string())
+            .ekill();
+    }
+    
     color!("\n";green "[ EXIT  ] Process successful.";"\n";,);
 }
 
-fn parse_release_tag(tag: &str, package: &str) -> Option<Version> {
-    tag.strip_prefix(package)
-        .and_then(|s| s.strip_prefix("-v"))
-        .and_then(|s| Version::parse(s).ok())
+/// Tags subcommand.
+///
+/// Helps migrate between tag formats, by listing the repo's
+/// tags which only match a legacy tag format of some package,
+/// and those which match no tag format at all.
+fn tags<P: AsRef<str>>(path: P) {
+    let pckg = PathBuf::from(path.as_ref());
+    let pckg = canonicalize(&pckg).ekill();
+    let repo = git::toplevel(&pckg);
+    info!("Reading tags of the repo at:\n{:?}", repo);
+    
+    // the packages, and the configs to read their tags with
+    let manifest_file = ManifestFile::new(pckg.join("Cargo.toml")).ekill();
+    let members = match manifest_file.is_workspace() {
+        true => Workspace::open(&pckg).ekill().members().to_vec(),
+        false => vec![pckg],
+    };
+    let packages = members.iter()
+        .map(|member| {
+            let manifest_file = ManifestFile::new(member.join("Cargo.toml")).ekill();
+            let config = Config::load(&repo, Some(&manifest_file)).ekill();
+            (manifest_file.name().ekill(), config)
+        })
+        .collect::<Vec<(String, Config)>>();
+    
+    let mut current = 0;
+    let mut legacy = Vec::new();
+    let mut unmatched = Vec::new();
+    for tag in exec!([&repo, "git tag -l"] | (preadlns)) {
+        if packages.iter()
+            .any(|(name, config)| config.parse_current_release_tag(&tag, name).is_some())
+        {
+            current += 1;
+            continue;
+        }
+        let migrated = packages.iter()
+            .find_map(|(name, config)| config.parse_release_tag(&tag, name)
+                .map(|version| config.release_tag(name, &version)));
+        match migrated {
+            Some(migrated) => legacy.push(format!("{} -> {}", tag, migrated)),
+            None => unmatched.push(tag),
+        };
+    }
+    
+    info!("{} tags are in the current tag format", current);
+    if !legacy.is_empty() {
+        info!("Tags in a legacy tag format, and their current format:\n{}", 
+            Lines(&legacy));
+    }
+    if !unmatched.is_empty() {
+        warn!("Tags which match no tag format, so are never read as releases:\n{}", 
+            Lines(&unmatched));
+    }
+}
+
+/// Garbage collection subcommand.
+///
+/// Lists scratch repos, and if given a maximum age in days,
+/// deletes those which are older, except for unfinished
+/// releases.
+fn gc(max_age_days: Option<u64>) {
+    let catch = catch_errors(false);
+    let tmp = tmp_dir();
+    info!("Looking for scratch repos in {:?}", tmp);
+    
+    let srps = list_scratch_repos(&tmp).ekill();
+    if srps.is_empty() {
+        info!("There are no scratch repos.");
+    }
+    let max_age = max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+    let mut deleted = 0;
+    for srp in &srps {
+        let unfinished = match srp.is_unfinished() {
+            true => ", unfinished release",
+            false => "",
+        };
+        info!("{:?} ({} old{})", srp.path, Age(srp.age), unfinished);
+        
+        if max_age.map(|max_age| srp.age > max_age).unwrap_or(false) {
+            if srp.is_unfinished() {
+                warn!("Not deleting unfinished release, finish it with \
+                    `deet resume` or delete it by hand");
+            } else {
+                remove_scratch_repo(&srp.path).ekill();
+                deleted += 1;
+            }
+        }
+    }
+    if max_age.is_some() {
+        info!("Deleted {} scratch repos", deleted);
+    }
+    
+    catch.handle(true);
 }
 
 fn main() {
     leet::init_from_env();
+    
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let options = Options::take(&mut args);
         
-    match_args!(match {
+    match_args!(match args {
         [] | ["--help"] => println!("{}", include_str!("../README.txt").trim()),
-        ["check", package] => run(package, None, MoistMeter::Dry),
+        ["check", package] => run(package, None, MoistMeter::Dry, &options),
         ["check", package, version] => {
-            let version = version.parse::<Version>().ekill();
-            run(package, Some(version), MoistMeter::Dry);
+            let version = version.parse::<VersionSpec>().ekill();
+            run(package, Some(version), MoistMeter::Dry, &options);
         },
         ["publish", package, version] => {
-            let version = version.parse::<Version>().ekill();
-            run(package, Some(version), MoistMeter::Wet);
+            let version = version.parse::<VersionSpec>().ekill();
+            run(package, Some(version), MoistMeter::Wet, &options);
         },
+        ["plan", package, version] => {
+            let version = version.parse::<VersionSpec>().ekill();
+            plan(package, version, &options);
+        },
+        ["check-workspace", workspace] => 
+            run_workspace(workspace, MoistMeter::Dry, &options),
+        ["publish-workspace", workspace] => 
+            run_workspace(workspace, MoistMeter::Wet, &options),
+        ["resume"] => resume(None, &options),
+        ["resume", scratch] => resume(Some(scratch), &options),
+        ["status"] => status(),
+        ["tags", path] => tags(path),
+        ["changelog", "lint", package] => changelog_lint(package),
+        ["changelog", "draft", package] => changelog_draft(package, false),
+        ["changelog", "draft", package, "--insert"] => changelog_draft(package, true),
+        ["gc"] => gc(None),
+        ["gc", max_age_days] => gc(Some(max_age_days.parse::<u64>()
+            .map_err(|e| format!("invalid max age in days {:?}: {}", max_age_days, e))
+            .ekill())),
         args => kill!("illegal cli args: {:?}", args),
     });
-}
\ No newline at end of file
+}
@@ -0,0 +1,326 @@
+//! Checking whether a manifest can be published, without
+//! building anything.
+
+use super::{ManifestFile, lookup, is_inherited};
+use std::path::Path;
+use toml_edit::Item;
+use semver::Version;
+
+/// Name which crates.io goes by in `publish` lists.
+const CRATES_IO: &str = "crates-io";
+
+/// Crate names which crates.io reserves.
+const RESERVED_NAMES: &[&str] = &[
+    "alloc", "core", "proc_macro", "proc-macro", "std", "test",
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4",
+    "com5", "com6", "com7", "com8", "com9", "lpt1", "lpt2", "lpt3",
+    "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Most keywords or categories crates.io accepts.
+const MAX_KEYWORDS: usize = 5;
+const MAX_CATEGORIES: usize = 5;
+/// Longest keyword crates.io accepts.
+const MAX_KEYWORD_LEN: usize = 20;
+/// Longest crate name crates.io accepts.
+const MAX_NAME_LEN: usize = 64;
+
+impl ManifestFile {
+    /// Find everything which would stop this package being
+    /// published to a registry, or crates.io if none.
+    ///
+    /// Inherited fields are audited as inherited, and paths in
+    /// them are relative to the workspace root.
+    pub fn audit(&self, registry: Option<&str>) -> Vec<String> {
+        let mut problems = Vec::new();
+        let crates_io = registry.unwrap_or(CRATES_IO) == CRATES_IO;
+
+        match self.name() {
+            Ok(name) => problems.extend(audit_name(&name, crates_io)),
+            Err(e) => problems.push(e.to_string()),
+        };
+        match self.version() {
+            Ok(version) => {
+                if let Err(e) = Version::parse(&version) {
+                    problems.push(format!("Version {:?} isn't semver: {}", version, e));
+                }
+            },
+            Err(e) => problems.push(e.to_string()),
+        };
+
+        // where the package may be published
+        if !self.publishable() {
+            problems.push("Package has publish = false".to_owned());
+        } else if let Some(registries) = self.publish_registries() {
+            let registry = registry.unwrap_or(CRATES_IO);
+            if !registries.iter().any(|r| r == registry) {
+                problems.push(format!("Package may only be published to {:?}, \
+                    not {:?}", registries, registry));
+            }
+        }
+
+        // metadata crates.io requires
+        let field = |key: &str| self.package_field(key).ok().flatten();
+        if crates_io {
+            let description = field("description");
+            if description.as_ref().and_then(Item::as_str).map(str::trim).unwrap_or("").is_empty() {
+                problems.push("Package has no description".to_owned());
+            }
+            if field("license").is_none() && field("license-file").is_none() {
+                problems.push("Package has no license or license-file".to_owned());
+            }
+        }
+
+        // files the manifest points to
+        for &(key, default) in &[
+            ("license-file", None),
+            ("readme", Some("README.md")),
+            ("build", Some("build.rs")),
+        ] {
+            let path = match field(key) {
+                Some(item) => match (item.as_str(), item.as_bool()) {
+                    (Some(path), _) => path.to_owned(),
+                    (None, Some(true)) => match default {
+                        Some(default) => default.to_owned(),
+                        None => continue,
+                    },
+                    (None, Some(false)) => continue,
+                    (None, None) => {
+                        problems.push(format!("Package {} is not a string", key));
+                        continue;
+                    },
+                },
+                None => continue,
+            };
+            let base = self.field_base(key);
+            if !base.join(&path).is_file() {
+                problems.push(format!("Package {} {:?} doesn't exist at:\n{:?}",
+                    key, path, base));
+            }
+        }
+
+        // limits crates.io puts on keywords and categories
+        if crates_io {
+            let keywords = self.audit_strings("keywords", &mut problems);
+            if keywords.len() > MAX_KEYWORDS {
+                problems.push(format!("Package has {} keywords, but crates.io \
+                    accepts at most {}", keywords.len(), MAX_KEYWORDS));
+            }
+            for keyword in &keywords {
+                problems.extend(audit_keyword(keyword));
+            }
+
+            let categories = self.audit_strings("categories", &mut problems);
+            if categories.len() > MAX_CATEGORIES {
+                problems.push(format!("Package has {} categories, but crates.io \
+                    accepts at most {}", categories.len(), MAX_CATEGORIES));
+            }
+        }
+
+        problems
+    }
+
+    /// Get a `[package]` field which should be an array of
+    /// strings, or nothing if it's missing.
+    fn audit_strings(&self, key: &str, problems: &mut Vec<String>) -> Vec<String> {
+        let item = match self.package_field(key) {
+            Ok(Some(item)) => item,
+            Ok(None) => return Vec::new(),
+            Err(e) => {
+                problems.push(e.to_string());
+                return Vec::new();
+            },
+        };
+        let strings = item.as_array()
+            .and_then(|array| array.iter()
+                .map(|value| value.as_str().map(String::from))
+                .collect::<Option<Vec<String>>>());
+        match strings {
+            Some(strings) => strings,
+            None => {
+                problems.push(format!("Package {} is not an array of strings", key));
+                Vec::new()
+            },
+        }
+    }
+
+    /// Get the directory which paths in a `[package]` field
+    /// are relative to.
+    fn field_base(&self, key: &str) -> &Path {
+        let inherited = lookup(&self.toml.borrow(), &["package", key])
+            .map(is_inherited)
+            .unwrap_or(false);
+        let manifest = match inherited {
+            true => self.inherit_from(),
+            false => self,
+        };
+        manifest.path.parent().unwrap_or_else(|| Path::new("."))
+    }
+}
+
+/// Check a crate name against cargo's and crates.io's rules.
+fn audit_name(name: &str, crates_io: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    match name.chars().next() {
+        None => problems.push("Package name is empty".to_owned()),
+        Some(c) if !c.is_ascii_alphabetic() => problems.push(format!(
+            "Package name {:?} doesn't start with a letter", name)),
+        Some(_) => (),
+    };
+    if name.chars().any(|c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')) {
+        problems.push(format!("Package name {:?} has characters other than \
+            letters, numbers, - and _", name));
+    }
+    if crates_io {
+        if name.len() > MAX_NAME_LEN {
+            problems.push(format!("Package name {:?} is longer than the {} \
+                characters crates.io accepts", name, MAX_NAME_LEN));
+        }
+        if RESERVED_NAMES.contains(&name.to_lowercase().as_str()) {
+            problems.push(format!("Package name {:?} is reserved by crates.io", name));
+        }
+    }
+    problems
+}
+
+/// Check a keyword against crates.io's rules.
+fn audit_keyword(keyword: &str) -> Option<String> {
+    let valid = keyword.len() <= MAX_KEYWORD_LEN
+        && keyword.chars().next().map(|c| c.is_ascii_alphanumeric()).unwrap_or(false)
+        && keyword.chars().all(|c| c.is_ascii_alphanumeric()
+            || c == '_' || c == '-' || c == '+');
+    match valid {
+        true => None,
+        false => Some(format!("Keyword {:?} isn't accepted by crates.io, which \
+            takes at most {} letters, numbers, _, - and +, starting with a \
+            letter or number", keyword, MAX_KEYWORD_LEN)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestDir;
+    
+    /// Audit a package manifest, written in a fresh directory,
+    /// for a registry.
+    fn audit(name: &str, package: &str, registry: Option<&str>) -> Vec<String> {
+        let dir = TestDir::new(name);
+        dir.write("README.md", "");
+        let path = dir.write("Cargo.toml", package);
+        ManifestFile::new(&path).unwrap().audit(registry)
+    }
+    
+    #[test]
+    fn audit_publishable() {
+        let package = "\
+[package]
+name = \"a\"
+version = \"1.0.0\"
+description = \"A package\"
+license = \"MIT\"
+readme = true
+keywords = [\"one\", \"two\"]
+";
+        assert!(audit("audit-ok", package, None).is_empty());
+        assert!(audit("audit-ok-local", package, Some("local")).is_empty());
+    }
+    
+    #[test]
+    fn audit_crates_io_metadata() {
+        let package = "\
+[package]
+name = \"a\"
+version = \"1.0.0\"
+description = \" \"
+";
+        let expected = vec![
+            "Package has no description",
+            "Package has no license or license-file",
+        ];
+        assert_eq!(audit("audit-metadata", package, None), expected);
+        assert_eq!(audit("audit-metadata-crates-io", package, Some("crates-io")), expected);
+        // other registries may not need them
+        assert!(audit("audit-metadata-local", package, Some("local")).is_empty());
+    }
+    
+    #[test]
+    fn audit_name_and_version() {
+        let problems = audit("audit-name", "\
+[package]
+name = \"1con\"
+version = \"1.0\"
+description = \"A package\"
+license = \"MIT\"
+", Some("local"));
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("doesn't start with a letter"));
+        assert!(problems[1].contains("isn't semver"));
+        
+        let package = "\
+[package]
+name = \"nul\"
+version = \"1.0.0\"
+description = \"A package\"
+license = \"MIT\"
+";
+        let problems = audit("audit-reserved", package, None);
+        assert_eq!(problems, vec!["Package name \"nul\" is reserved by crates.io"]);
+        assert!(audit("audit-reserved-local", package, Some("local")).is_empty());
+    }
+    
+    #[test]
+    fn audit_publish_field() {
+        let package = |publish: &str| format!("\
+[package]
+name = \"a\"
+version = \"1.0.0\"
+description = \"A package\"
+license = \"MIT\"
+publish = {}
+", publish);
+        assert_eq!(audit("audit-publish-false", &package("false"), None),
+            vec!["Package has publish = false"]);
+        
+        let only_local = package("[\"local\"]");
+        assert!(audit("audit-publish-local", &only_local, Some("local")).is_empty());
+        let problems = audit("audit-publish-crates-io", &only_local, None);
+        assert_eq!(problems, vec!["Package may only be published to [\"local\"], \
+            not \"crates-io\""]);
+    }
+    
+    #[test]
+    fn audit_missing_files() {
+        let problems = audit("audit-files", "\
+[package]
+name = \"a\"
+version = \"1.0.0\"
+description = \"A package\"
+license-file = \"LICENSE\"
+readme = \"README.md\"
+build = true
+", None);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("Package license-file \"LICENSE\" doesn't exist"));
+        assert!(problems[1].starts_with("Package build \"build.rs\" doesn't exist"));
+    }
+    
+    #[test]
+    fn audit_keywords_and_categories() {
+        let package = "\
+[package]
+name = \"a\"
+version = \"1.0.0\"
+description = \"A package\"
+license = \"MIT\"
+keywords = [\"a\", \"b\", \"c\", \"d\", \"e\", \"-f\"]
+categories = \"none\"
+";
+        let problems = audit("audit-keywords", package, None);
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("Package has 6 keywords"));
+        assert!(problems[1].starts_with("Keyword \"-f\" isn't accepted"));
+        assert_eq!(problems[2], "Package categories is not an array of strings");
+        assert!(audit("audit-keywords-local", package, Some("local")).is_empty());
+    }
+}
@@ -1,73 +1,174 @@
 
 //! Reading and editing cargo manifest files.
 
+/// Cargo workspaces.
+pub mod workspace;
+/// Auditing manifests before publishing.
+pub mod audit;
+
+pub use workspace::Workspace;
+
 use std::{
     path::{Path, PathBuf},
-    cell::RefCell,
-    fs::{self, read_to_string},
+    cell::{Cell, RefCell},
+    fs::{self, read_to_string, canonicalize},
+    env::current_dir,
     iter::FromIterator,
-    fmt::{self, Debug, Formatter},
+    fmt::{self, Debug, Display, Formatter},
     str::FromStr,
 };
 use toml_edit::{
-    Document,
+    DocumentMut,
     Item, Value, Key,
 };
 use failure::{Error, format_err};
 
 /// Allows reading and editing a cargo manifest file.
+///
+/// Values which a package inherits from its workspace are
+/// read from, and edited in, the workspace root manifest.
 pub struct ManifestFile {
     path: PathBuf,
-    toml: RefCell<Document>,
+    toml: RefCell<DocumentMut>,
+    /// Root manifest of the workspace, if this is a package
+    /// within some other manifest's workspace.
+    workspace: Option<Box<ManifestFile>>,
+    /// Whether there are unsaved edits.
+    dirty: Cell<bool>,
 }
 
 impl ManifestFile {
-    /// Open a manifest file.
+    /// Open a manifest file, and the root manifest of its
+    /// workspace.
     pub fn new<P>(path: P) -> Result<Self, Error>
     where
         P: AsRef<Path>
     {
-        let toml = read_to_string(&path)
+        let mut manifest = ManifestFile::open(path.as_ref())?;
+        manifest.workspace = manifest.find_workspace_root()?
+            .map(Box::new);
+        Ok(manifest)
+    }
+    
+    /// Open a manifest file, without its workspace.
+    fn open(path: &Path) -> Result<Self, Error> {
+        let toml = read_to_string(path)
             .map_err(Error::from)?
-            .parse::<Document>()
+            .parse::<DocumentMut>()
             .map_err(Error::from)?;
-            
-        if !toml.as_table().contains_key("dependencies") {
-            return Err(format_err!(
-                "no dependencies section in manifest at:\n\
-                {:?}", path.as_ref()));
-        }
         
         Ok(ManifestFile {
-            path: path.as_ref().to_owned(),
+            path: path.to_owned(),
             toml: RefCell::new(toml),
+            workspace: None,
+            dirty: Cell::new(false),
         })
     }
     
-    /// Iterate dependency editors.
+    /// Find the root manifest of the workspace this package
+    /// is in, like cargo does, unless it's this one.
+    fn find_workspace_root(&self) -> Result<Option<ManifestFile>, Error> {
+        let doc = self.toml.borrow();
+        if doc.contains_key("workspace") || !doc.contains_key("package") {
+            return Ok(None);
+        }
+        let dir = self.path.parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(canonicalize)
+            .unwrap_or_else(current_dir)
+            .map_err(Error::from)?;
+        
+        if let Some(root) = lookup(&doc, &["package", "workspace"]).and_then(Item::as_str) {
+            return ManifestFile::open(&dir.join(root).join("Cargo.toml"))
+                .map(Some);
+        }
+        for ancestor in dir.ancestors().skip(1) {
+            let path = ancestor.join("Cargo.toml");
+            if path.is_file() {
+                let root = ManifestFile::open(&path)?;
+                if root.toml.borrow().as_table().contains_key("workspace") {
+                    return Ok(Some(root));
+                }
+            }
+        }
+        Ok(None)
+    }
+    
+    /// Get the path of the underlying manifest file.
+    pub fn path(&self) -> &Path { &self.path }
+    
+    /// Whether this is the root manifest of a workspace.
+    pub fn is_workspace(&self) -> bool {
+        self.toml.borrow().contains_key("workspace")
+    }
+    
+    /// Get the root manifest of the workspace, if this is a 
+    /// package within some other manifest's workspace.
+    pub fn workspace_root(&self) -> Option<&ManifestFile> {
+        self.workspace.as_deref()
+    }
+    
+    /// The manifest which inherited values come from.
+    fn inherit_from(&self) -> &ManifestFile {
+        self.workspace.as_deref().unwrap_or(self)
+    }
+    
+    /// Get a `[package]` field, or the `[workspace.package]`
+    /// field it inherits.
+    fn package_field(&self, key: &str) -> Result<Option<Item>, Error> {
+        let item = lookup(&self.toml.borrow(), &["package", key])
+            .cloned()
+            .unwrap_or_default();
+        if !is_inherited(&item) {
+            return Ok(Some(item).filter(|item| !item.is_none()));
+        }
+        let root = self.inherit_from();
+        let inherited = lookup(&root.toml.borrow(), &["workspace", "package", key])
+            .cloned()
+            .unwrap_or_default();
+        if inherited.is_none() {
+            return Err(format_err!("package {} is inherited, but workspace.package \
+                is missing it at:\n{:?}", key, root.path));
+        }
+        Ok(Some(inherited))
+    }
+
+    /// Iterate dependency editors, from every dependency
+    /// table.
+    ///
+    /// This covers the dependencies, dev-dependencies, and
+    /// build-dependencies tables, both at the top level and
+    /// for each target. Missing tables have no dependencies.
     pub fn deps<'s>(&'s self) 
         -> Result<impl Iterator<Item=Dep<'s>> + 's, Error> 
     {
         let doc = self.toml.borrow();
-        let deps = doc["dependencies"].as_table_like()
-            .ok_or_else(|| format_err!("dependencies is \
-                not a table-like at:\n{:?}", self.path))?
-            .iter()
-            .flat_map(|(key, value)| 
-                parse_dep(self, key, value))
-            .collect::<Vec<Dep<'s>>>();
+        let mut deps = Vec::new();
+        for table in dep_tables(&doc) {
+            let item = match table_item(&doc, &table) {
+                Some(item) => item,
+                None => continue,
+            };
+            let entries = item.as_table_like()
+                .ok_or_else(|| format_err!("{} is not a table-like \
+                    at:\n{:?}", table, self.path))?;
+            deps.extend(entries.iter()
+                .flat_map(|(key, value)| 
+                    parse_dep(self, &table, key, value)));
+        }
         Ok(deps.into_iter())
     }
     
     /// Get a dependency editor by key.
-    pub fn dep(&self, key: &DepKey) -> Result<Dep, Error> {
+    pub fn dep(&self, key: &DepKey) -> Result<Dep<'_>, Error> {
         let doc = self.toml.borrow();
-        let dep = doc["dependencies"].as_table_like()
-            .ok_or_else(|| format_err!("dependencies is \
-                not a table-like at:\n{:?}", self.path))?
+        let dep = table_item(&doc, &key.table)
+            .and_then(Item::as_table_like)
+            .ok_or_else(|| format_err!("{} is \
+                not a table-like at:\n{:?}", key.table, self.path))?
             .iter()
-            .flat_map(|(key, value)| 
-                parse_dep(self, key, value))
+            .flat_map(|(k, value)| 
+                parse_dep(self, &key.table, k, value))
             .find(|dep| dep.key == key.key)
             .ok_or_else(|| 
                 format_err!("cannot find dependency:\n\
@@ -78,7 +179,8 @@ impl ManifestFile {
     /// Get the name of this package.
     pub fn name(&self) -> Result<String, Error> {
         let doc = self.toml.borrow();
-        doc["package"].as_table_like()
+        doc.get("package")
+            .and_then(Item::as_table_like)
             .ok_or_else(|| format_err!("package is not a table-like \
                 at:\n{:?}", self.path))
             .and_then(|table| table.get("name")
@@ -90,66 +192,202 @@ impl ManifestFile {
                     at:\n{:?}", self.path)))
     }
     
-    /// Get the current version of this package.
+    /// Get the current version of this package, which may 
+    /// be inherited from the workspace.
     pub fn version(&self) -> Result<String, Error> {
-        let doc = self.toml.borrow();
-        doc["package"].as_table_like()
-            .ok_or_else(|| format_err!("package is not a table-like \
+        if self.toml.borrow().get("package").and_then(Item::as_table_like).is_none() {
+            return Err(format_err!("package is not a table-like \
+                at:\n{:?}", self.path));
+        }
+        self.package_field("version")?
+            .ok_or_else(|| format_err!("package is missing version \
                 at:\n{:?}", self.path))
-            .and_then(|table| table.get("version")
-                .ok_or_else(|| format_err!("package is missing version \
-                    at:\n{:?}", self.path)))
             .and_then(|item| item.as_str()
                 .map(String::from)
                 .ok_or_else(|| format_err!("version is not string \
                     at:\n{:?}", self.path)))
     }
     
+    /// Whether the version of this package is inherited from
+    /// the workspace.
+    pub fn version_is_inherited(&self) -> bool {
+        lookup(&self.toml.borrow(), &["package", "version"])
+            .map(is_inherited)
+            .unwrap_or(false)
+    }
+    
+    /// Whether this package may be published at all.
+    ///
+    /// False for `publish = false` and `publish = []`.
+    pub fn publishable(&self) -> bool {
+        match self.package_field("publish").ok().flatten() {
+            Some(item) => item.as_bool()
+                .or_else(|| item.as_array().map(|a| !a.is_empty()))
+                .unwrap_or(true),
+            None => true,
+        }
+    }
+
+    /// Get the registries this package may be published to,
+    /// if restricted with `publish = [...]`.
+    pub fn publish_registries(&self) -> Option<Vec<String>> {
+        self.package_field("publish").ok().flatten()?
+            .as_array()
+            .map(|array| array.iter()
+                .filter_map(|value| value.as_str().map(String::from))
+                .collect())
+    }
+
+    /// Get the `[package.metadata]` table of some tool, if
+    /// present.
+    pub fn metadata(&self, tool: &str) -> Option<Item> {
+        let doc = self.toml.borrow();
+        lookup(&doc, &["package", "metadata", tool]).cloned()
+    }
+
     /// Set the current version of this package.
+    ///
+    /// If the version is inherited, in any form, it's set in
+    /// the `[workspace.package]` table of the workspace root,
+    /// in whichever form that's written. Formatting around the
+    /// version, such as comments, is kept.
     pub fn set_version(&self, version: &str) -> Result<(), Error> {
-        let mut doc = self.toml.borrow_mut();
-        let lib = &mut doc["package"];
-        
-        if lib.is_table() {
-            let table = lib.as_table_mut().unwrap();
-            table["version"] = Item::Value(Value::from(version));
-        } else if lib.is_inline_table() {
-            let mut table = lib.as_inline_table_mut().unwrap().clone();
-            
-            Value::from_iter(vec![(
-                &Key::from_str("version").unwrap(), 
-                version
-            )])
-                .as_inline_table_mut()
-                .unwrap()
-                .merge_into(&mut table);
-        } else {
-            return Err(format_err!("package is not at table-like \
-                at:\n{:?}", self.path));
+        let (manifest, keys): (&ManifestFile, &[&str]) = match self.version_is_inherited() {
+            true => (self.inherit_from(), &["workspace", "package"]),
+            false => (self, &["package"]),
+        };
+        let mut doc = manifest.toml.borrow_mut();
+        let mut item = doc.as_item_mut();
+        for key in keys {
+            item = item.get_mut(key)
+                .ok_or_else(|| format_err!("{} is missing at:\n{:?}", 
+                    keys.join("."), manifest.path))?;
+        }
+        if let Some(inline) = item.as_inline_table_mut() {
+            if !inline.contains_key("version") {
+                // the space before the closing brace moves after
+                // the new last value
+                let mut value = Value::from(version);
+                if let Some((_, last)) = inline.iter_mut().last() {
+                    if let Some(suffix) = last.decor().suffix().cloned() {
+                        last.decor_mut().set_suffix("");
+                        value.decor_mut().set_suffix(suffix);
+                    }
+                }
+                inline.insert("version", value);
+                manifest.dirty.set(true);
+                return Ok(());
+            }
         }
+        let table = item.as_table_like_mut()
+            .ok_or_else(|| format_err!("{} is not a table-like at:\n{:?}",
+                keys.join("."), manifest.path))?;
         
+        match table.get_mut("version").and_then(Item::as_value_mut) {
+            Some(value) => {
+                let decor = value.decor().clone();
+                *value = Value::from(version);
+                *value.decor_mut() = decor;
+            },
+            None => {
+                table.insert("version", Item::Value(Value::from(version)));
+            },
+        };
+        manifest.dirty.set(true);
         Ok(())
     }
     
-    /// Save to the underlying manifest file.
+    /// Save to the underlying manifest file, and to the
+    /// workspace root if it was edited.
     pub fn save(&mut self) -> Result<(), Error> {
         let doc = self.toml.borrow();
         let content = doc.to_string();
         fs::write(&self.path, content)
-            .map_err(Error::from)
+            .map_err(Error::from)?;
+        self.dirty.set(false);
+        
+        if let Some(root) = self.workspace.as_mut() {
+            if root.dirty.get() {
+                root.save()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// List the dependency tables a manifest may have.
+fn dep_tables(doc: &DocumentMut) -> Vec<DepTable> {
+    let kinds = [DepKind::Normal, DepKind::Dev, DepKind::Build];
+    let mut tables: Vec<DepTable> = kinds.iter()
+        .map(|&kind| DepTable { target: None, kind })
+        .collect();
+    if let Some(targets) = doc.get("target").and_then(Item::as_table_like) {
+        for (target, _) in targets.iter() {
+            tables.extend(kinds.iter()
+                .map(|&kind| DepTable { target: Some(target.to_owned()), kind }));
+        }
     }
+    tables
+}
+
+/// Get a dependency table, if present.
+fn table_item<'d>(doc: &'d DocumentMut, table: &DepTable) -> Option<&'d Item> {
+    match table.target {
+        Some(ref target) => lookup(doc, &["target", target, table.kind.table_name()]),
+        None => lookup(doc, &[table.kind.table_name()]),
+    }
+}
+
+/// Get an item by its path of keys, if present.
+fn lookup<'d>(doc: &'d DocumentMut, keys: &[&str]) -> Option<&'d Item> {
+    let mut item = doc.as_item();
+    for key in keys {
+        item = item.get(key)?;
+    }
+    Some(item)
+}
+
+/// Whether an item is `{ workspace = true }`, and so
+/// inherited from the workspace.
+fn is_inherited(item: &Item) -> bool {
+    item.as_table_like()
+        .and_then(|table| table.get("workspace"))
+        .and_then(Item::as_bool)
+        .unwrap_or(false)
 }
 
 fn parse_dep<'m>(
     manifest: &'m ManifestFile,
+    dep_table: &DepTable,
+    key: &str,
+    value: &Item,
+) -> Option<Dep<'m>> {
+    if is_inherited(value) {
+        let inherited = lookup(
+            &manifest.inherit_from().toml.borrow(), 
+            &["workspace", "dependencies", key])
+            .cloned()
+            .unwrap_or_default();
+        return parse_dep_entry(manifest, dep_table, key, &inherited, true);
+    }
+    parse_dep_entry(manifest, dep_table, key, value, false)
+}
+
+fn parse_dep_entry<'m>(
+    manifest: &'m ManifestFile,
+    dep_table: &DepTable,
     key: &str,
     value: &Item,
+    inherited: bool,
 ) -> Option<Dep<'m>> {
     value.as_str()
         .map(|version| Dep {
             manifest,
+            table: dep_table.clone(),
             key: key.into(),
+            inherited,
             package: key.into(),
+            registry: None,
             source: DepSource::Crates { 
                 version: version.to_owned(),
             },
@@ -160,34 +398,96 @@ fn parse_dep<'m>(
                     .and_then(Item::as_str)
                     .unwrap_or(key)
                     .to_string();
-                table.get("version")
+                let registry = table.get("registry")
                     .and_then(Item::as_str)
-                    .map(|version| DepSource::Crates {
-                        version: version.to_owned(),
-                    })
-                    .or_else(|| table.get("path")
-                        .and_then(Item::as_str)
-                        .map(|path| DepSource::Local {
-                            path: path.to_owned(),
-                        }))
+                    .map(String::from);
+                let version = table.get("version")
+                    .and_then(Item::as_str)
+                    .map(String::from);
+                let path = table.get("path")
+                    .and_then(Item::as_str)
+                    .map(String::from);
+                match (path, version) {
+                    (Some(path), Some(version)) => Some(DepSource::LocalWithVersion {
+                        path,
+                        version,
+                    }),
+                    (Some(path), None) => Some(DepSource::Local { path }),
+                    (None, Some(version)) => Some(match registry.clone() {
+                        Some(registry) => DepSource::Registry {
+                            version,
+                            registry,
+                        },
+                        None => DepSource::Crates { version },
+                    }),
+                    (None, None) => None,
+                }
                     .map(|source| Dep {
                         manifest,
+                        table: dep_table.clone(),
                         key: key.into(),
+                        inherited,
                         package,
+                        registry,
                         source,
                     })
             }))
 }
 
+/// Kind of dependency table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum DepKind {
+    Normal,
+    Dev,
+    Build,
+}
+
+impl DepKind {
+    /// Get the name of tables of this kind.
+    pub fn table_name(self) -> &'static str {
+        match self {
+            DepKind::Normal => "dependencies",
+            DepKind::Dev => "dev-dependencies",
+            DepKind::Build => "build-dependencies",
+        }
+    }
+}
+
+/// A dependency table in a manifest file.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DepTable {
+    /// Target cfg or triple, for target-specific tables.
+    pub target: Option<String>,
+    pub kind: DepKind,
+}
+
+impl Display for DepTable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(target) = self.target.as_ref() {
+            write!(f, "target.{:?}.", target)?;
+        }
+        f.write_str(self.kind.table_name())
+    }
+}
+
 /// Identifying key for a manifest dependency.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct DepKey { key: String }
+pub struct DepKey { table: DepTable, key: String }
+
+impl DepKey {
+    /// Get the table the dependency is in.
+    pub fn table(&self) -> &DepTable { &self.table }
+}
 
 /// A dependency in a manifest file.
 pub struct Dep<'a> {
     manifest: &'a ManifestFile,
+    table: DepTable,
     key: String,
+    /// Whether inherited from `[workspace.dependencies]`.
+    inherited: bool,
     package: String,
+    registry: Option<String>,
     source: DepSource,
 }
 
@@ -195,8 +495,11 @@ impl<'a> Debug for Dep<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("Dep")
             .field("manifest", &self.manifest.path)
+            .field("table", &self.table)
             .field("key", &self.key)
+            .field("inherited", &self.inherited)
             .field("package", &self.package)
+            .field("registry", &self.registry)
             .field("source", &self.source)
             .finish()
     }
@@ -205,35 +508,47 @@ impl<'a> Debug for Dep<'a> {
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum DepSource {
     Crates { version: String },
+    /// From a registry other than crates.io.
+    Registry { version: String, registry: String },
     Local { path: String },
+    /// Local, but with a version requirement, which cargo
+    /// publish uses instead of the path.
+    LocalWithVersion { path: String, version: String },
 }
 
 impl DepSource {
     pub fn is_crate(&self) -> bool {
-        match self {
-            &DepSource::Crates { .. } => true,
-            _ => false,
-        }
+        matches!(self, DepSource::Crates { .. })
     }
     
     pub fn is_local(&self) -> bool {
-        match self {
-            &DepSource::Local { .. } => true,
-            _ => false,
-        }
+        matches!(self, DepSource::Local { .. })
     }
     
+    /// Version requirement, for crates.io or another registry,
+    /// which may be alongside a local path.
     pub fn crate_version(&self) -> Option<&str> {
         match self {
-            &DepSource::Crates { ref version } => 
+            DepSource::Crates { version } |
+            DepSource::Registry { version, .. } |
+            DepSource::LocalWithVersion { version, .. } => 
                 Some(version.as_str()),
             _ => None,
         }
     }
     
+    pub fn registry(&self) -> Option<&str> {
+        match self {
+            DepSource::Registry { registry, .. } => 
+                Some(registry.as_str()),
+            _ => None,
+        }
+    }
+    
     pub fn local_path(&self) -> Option<&str> {
         match self {
-            &DepSource::Local { ref path } => 
+            DepSource::Local { path } |
+            DepSource::LocalWithVersion { path, .. } => 
                 Some(path.as_str()),
             _ => None,
         }
@@ -244,9 +559,15 @@ impl<'a> Dep<'a> {
     /// Get the package name.
     pub fn package(&self) -> &str { &self.package }
     
+    /// Get the table this dependency is in.
+    pub fn table(&self) -> &DepTable { &self.table }
+    
     /// Get the dependency key.
     pub fn key(&self) -> DepKey {
-        DepKey { key: self.key.clone() }
+        DepKey { 
+            table: self.table.clone(),
+            key: self.key.clone(),
+        }
     }
     
     /// Get the package source
@@ -254,34 +575,85 @@ impl<'a> Dep<'a> {
         self.source.clone()
     }
     
+    /// Whether this dependency is inherited from the
+    /// workspace, in which case it's edited there.
+    pub fn is_inherited(&self) -> bool { self.inherited }
+    
+    /// Get the local path, if the source is local, relative to
+    /// the manifest it's declared in.
+    pub fn local_path(&self) -> Option<PathBuf> {
+        let manifest = match self.inherited {
+            true => self.manifest.inherit_from(),
+            false => self.manifest,
+        };
+        let dir = manifest.path.parent().unwrap_or_else(|| Path::new(""));
+        self.source.local_path().map(|path| dir.join(path))
+    }
+    
+    /// Get the `registry` key, which may be present even
+    /// if the source is local.
+    pub fn registry(&self) -> Option<&str> {
+        self.registry.as_deref()
+    }
+    
     /// Edit the document, changing the package source.
     ///
-    /// Changes must still be saved through the underlying
-    /// `ManifestFile`.
+    /// An inherited dependency is changed in the workspace 
+    /// root. Changes must still be saved through the 
+    /// underlying `ManifestFile`.
+    ///
+    /// The `registry` key is kept alongside a local path with
+    /// a version, but removed otherwise, unless the new source
+    /// is a registry.
     pub fn set_source(&mut self, source: DepSource) {
-        let mut doc = self.manifest.toml.borrow_mut();
-        
-        // determine the key/val to insert
-        let (key, val) = match source.clone() {
-            DepSource::Crates { version } => (
-                "version", Value::from(version) ),
-            DepSource::Local { path } => (
-                "path", Value::from(path) ),
+        let manifest = match self.inherited {
+            true => self.manifest.inherit_from(),
+            false => self.manifest,
         };
+        let mut doc = manifest.toml.borrow_mut();
+        manifest.dirty.set(true);
         
-        let entry = &mut doc["dependencies"][&self.key];
+        // determine the key/vals to insert
+        let fields: Vec<(Key, Value)> = match source.clone() {
+            DepSource::Crates { version } => vec![
+                ("version", Value::from(version)) ],
+            DepSource::Registry { version, registry } => vec![
+                ("version", Value::from(version)),
+                ("registry", Value::from(registry)) ],
+            DepSource::Local { path } => vec![
+                ("path", Value::from(path)) ],
+            DepSource::LocalWithVersion { path, version } => vec![
+                ("path", Value::from(path)),
+                ("version", Value::from(version)) ]
+                .into_iter()
+                .chain(self.registry.clone()
+                    .map(|registry| ("registry", Value::from(registry))))
+                .collect(),
+        }
+            .into_iter()
+            .map(|(key, val)| (Key::from_str(key).unwrap(), val))
+            .collect();
+        let inline = || Value::from_iter(fields.iter()
+            .map(|(key, val)| (key.clone(), val.clone())));
+        
+        let kind = self.table.kind.table_name();
+        let entry = match (self.inherited, self.table.target.as_ref()) {
+            (true, _) => &mut doc["workspace"]["dependencies"][&self.key],
+            (false, Some(target)) => &mut doc["target"][target.as_str()][kind][&self.key],
+            (false, None) => &mut doc[kind][&self.key],
+        };
         let replacement: Item = if entry.is_str() {
-            Item::Value(Value::from_iter(vec![(
-                &Key::from_str(key).unwrap(), 
-                val
-            )]))
+            Item::Value(inline())
         } else if entry.is_table() {
             let mut table = entry.as_table()
                 .unwrap().clone();
                 
             table.remove("version");
             table.remove("path");
-            table[key] = Item::Value(val);
+            table.remove("registry");
+            for (key, val) in &fields {
+                table[key.get()] = Item::Value(val.clone());
+            }
             
             Item::Table(table)
         } else if entry.is_inline_table() {
@@ -290,21 +662,190 @@ impl<'a> Dep<'a> {
                 
             table.remove("version");
             table.remove("path");
+            table.remove("registry");
             
-            Value::from_iter(vec![(
-                &Key::from_str(key).unwrap(), 
-                val
-            )])
-                .as_inline_table_mut()
-                .unwrap()
-                .merge_into(&mut table);
+            for (key, val) in &fields {
+                table.insert(key.get(), val.clone());
+            }
                 
             Item::Value(table.into())
         } else {
             unreachable!()
         };
         
-        *entry = replacement.into();
+        *entry = replacement;
+        if !matches!(source, DepSource::LocalWithVersion { .. }) {
+            self.registry = source.registry().map(String::from);
+        }
         self.source = source;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestDir;
+    
+    /// Write a workspace root manifest, if any, and a package
+    /// manifest in a fresh directory, set the package version,
+    /// and return both manifests as saved.
+    fn set_version(name: &str, root: Option<&str>, package: &str) -> (String, String) {
+        let dir = TestDir::new(name);
+        if let Some(root) = root {
+            dir.write("Cargo.toml", root);
+        }
+        let path = dir.write("a/Cargo.toml", package);
+        
+        let mut manifest = ManifestFile::new(&path).unwrap();
+        manifest.set_version("2.0.0").unwrap();
+        manifest.save().unwrap();
+        assert_eq!(ManifestFile::new(&path).unwrap().version().unwrap(), "2.0.0");
+        
+        (dir.read("Cargo.toml"), dir.read("a/Cargo.toml"))
+    }
+    
+    #[test]
+    fn set_version_table() {
+        let (_, package) = set_version("table", None, "\
+[package]
+name = \"a\"
+version   =   \"1.0.0\" # the version
+edition = \"2021\"
+");
+        assert_eq!(package, "\
+[package]
+name = \"a\"
+version   =   \"2.0.0\" # the version
+edition = \"2021\"
+");
+    }
+    
+    #[test]
+    fn set_version_table_missing() {
+        let (_, package) = set_version("table-missing", None, "\
+[package]
+name = \"a\"
+
+[dependencies]
+");
+        assert_eq!(package, "\
+[package]
+name = \"a\"
+version = \"2.0.0\"
+
+[dependencies]
+");
+    }
+    
+    #[test]
+    fn set_version_inline_table() {
+        let (_, package) = set_version("inline", None, "\
+package = { name = \"a\",  version = \"1.0.0\" } # the package
+");
+        assert_eq!(package, "\
+package = { name = \"a\",  version = \"2.0.0\" } # the package
+");
+    }
+    
+    #[test]
+    fn set_version_inline_table_missing() {
+        let (_, package) = set_version("inline-missing", None, "\
+package = { name = \"a\" }
+");
+        assert_eq!(package, "\
+package = { name = \"a\", version = \"2.0.0\" }
+");
+    }
+    
+    const ROOT: &str = "\
+[workspace]
+members = [\"a\"]
+
+[workspace.package]
+version = \"1.0.0\" # shared
+edition = \"2021\"
+";
+    
+    const ROOT_EDITED: &str = "\
+[workspace]
+members = [\"a\"]
+
+[workspace.package]
+version = \"2.0.0\" # shared
+edition = \"2021\"
+";
+    
+    #[test]
+    fn set_version_inherited_dotted() {
+        let package = "\
+[package]
+name = \"a\"
+version.workspace = true
+";
+        let (root, edited) = set_version("inherited-dotted", Some(ROOT), package);
+        assert_eq!(root, ROOT_EDITED);
+        assert_eq!(edited, package);
+    }
+    
+    #[test]
+    fn set_version_inherited_inline() {
+        let package = "\
+[package]
+name = \"a\"
+version = { workspace = true }
+";
+        let (root, edited) = set_version("inherited-inline", Some(ROOT), package);
+        assert_eq!(root, ROOT_EDITED);
+        assert_eq!(edited, package);
+    }
+    
+    #[test]
+    fn set_version_inherited_table() {
+        let package = "\
+[package]
+name = \"a\"
+
+[package.version]
+workspace = true
+";
+        let (root, edited) = set_version("inherited-table", Some(ROOT), package);
+        assert_eq!(root, ROOT_EDITED);
+        assert_eq!(edited, package);
+    }
+    
+    #[test]
+    fn set_version_inherited_from_dotted() {
+        let (root, _) = set_version("inherited-from-dotted", Some("\
+[workspace]
+members = [\"a\"]
+package.version = \"1.0.0\"
+"), "\
+[package]
+name = \"a\"
+version.workspace = true
+");
+        assert_eq!(root, "\
+[workspace]
+members = [\"a\"]
+package.version = \"2.0.0\"
+");
+    }
+    
+    #[test]
+    fn set_version_inherited_from_inline() {
+        let (root, _) = set_version("inherited-from-inline", Some("\
+[workspace]
+members = [\"a\"]
+package = { version = \"1.0.0\", edition = \"2021\" }
+"), "\
+[package]
+name = \"a\"
+version.workspace = true
+");
+        assert_eq!(root, "\
+[workspace]
+members = [\"a\"]
+package = { version = \"2.0.0\", edition = \"2021\" }
+");
+    }
+}
@@ -0,0 +1,236 @@
+//! Reading cargo workspace manifests.
+
+use super::{ManifestFile, DepKind};
+use crate::util::path::glob_dirs;
+use std::{
+    path::{Path, PathBuf},
+    fs::{read_to_string, canonicalize},
+    collections::{BTreeMap, BTreeSet},
+};
+use toml_edit::{DocumentMut, Item};
+use failure::{Error, format_err};
+
+/// A cargo workspace, and the packages within it.
+pub struct Workspace {
+    members: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// Open the workspace whose root manifest is in the given
+    /// directory.
+    pub fn open<P>(root: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>
+    {
+        let root = canonicalize(&root).map_err(Error::from)?;
+        let path = root.join("Cargo.toml");
+        let toml = read_to_string(&path)
+            .map_err(Error::from)?
+            .parse::<DocumentMut>()
+            .map_err(Error::from)?;
+        let workspace = toml.as_table().get("workspace")
+            .and_then(Item::as_table_like)
+            .ok_or_else(|| format_err!("no workspace section in \
+                manifest at:\n{:?}", path))?;
+
+        let patterns = |key: &str| -> Result<Vec<String>, Error> {
+            match workspace.get(key) {
+                None => Ok(Vec::new()),
+                Some(item) => item.as_array()
+                    .and_then(|array| array.iter()
+                        .map(|value| value.as_str().map(String::from))
+                        .collect::<Option<Vec<String>>>())
+                    .ok_or_else(|| format_err!("workspace {} is not an \
+                        array of strings at:\n{:?}", key, path)),
+            }
+        };
+
+        let mut excluded = BTreeSet::new();
+        for pattern in patterns("exclude")? {
+            for dir in glob_dirs(&root, &pattern)? {
+                excluded.insert(canonicalize(dir).map_err(Error::from)?);
+            }
+        }
+
+        let mut members = BTreeSet::new();
+        for pattern in patterns("members")? {
+            for dir in glob_dirs(&root, &pattern)? {
+                let dir = canonicalize(dir).map_err(Error::from)?;
+                if dir.join("Cargo.toml").is_file() && !excluded.contains(&dir) {
+                    members.insert(dir);
+                }
+            }
+        }
+        if toml.as_table().contains_key("package") {
+            members.insert(root);
+        }
+
+        Ok(Workspace {
+            members: members.into_iter().collect(),
+        })
+    }
+
+    /// Get the directories of all member packages.
+    pub fn members(&self) -> &[PathBuf] { &self.members }
+
+    /// Sort the members so that every member comes after
+    /// all members it depends on through a local path.
+    ///
+    /// Path-only dev-dependencies of members for which
+    /// `drops_dev_deps` is true are ignored, since cargo publish
+    /// drops them, and allows cycles through them.
+    ///
+    /// Members with no ordering between them stay sorted by
+    /// path, so the order is stable between runs.
+    pub fn release_order<F>(&self, mut drops_dev_deps: F) -> Result<Vec<PathBuf>, Error>
+    where
+        F: FnMut(&ManifestFile) -> Result<bool, Error>
+    {
+        // member -> members it depends on
+        let mut deps: BTreeMap<&Path, BTreeSet<PathBuf>> = BTreeMap::new();
+        for member in &self.members {
+            let manifest = ManifestFile::new(member.join("Cargo.toml"))?;
+            let drops_dev_deps = drops_dev_deps(&manifest)?;
+            let mut member_deps = BTreeSet::new();
+            for dep in manifest.deps()? {
+                if dep.table().kind == DepKind::Dev 
+                    && dep.source().is_local() 
+                    && drops_dev_deps 
+                {
+                    continue;
+                }
+                let path = match dep.local_path() {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let path = canonicalize(&path)
+                    .map_err(|e| format_err!("cannot find local dependency \
+                        {:?} of {:?}:\n{}", dep.package(), member, e))?;
+                if self.members.contains(&path) && &path != member {
+                    member_deps.insert(path);
+                }
+            }
+            deps.insert(member, member_deps);
+        }
+
+        let mut order = Vec::new();
+        while !deps.is_empty() {
+            let ready: Vec<&Path> = deps.iter()
+                .filter(|(_, member_deps)| member_deps.is_empty())
+                .map(|(&member, _)| member)
+                .collect();
+            if ready.is_empty() {
+                return Err(format_err!("dependency cycle between \
+                    workspace members:\n{:#?}", deps.keys().collect::<Vec<_>>()));
+            }
+            for member in ready {
+                deps.remove(member);
+                for member_deps in deps.values_mut() {
+                    member_deps.remove(member);
+                }
+                order.push(member.to_owned());
+            }
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestDir;
+    
+    /// Write a package manifest, with local path dependencies
+    /// and dev-dependencies on other members.
+    fn package(dir: &TestDir, path: &str, deps: &[&str], dev_deps: &[&str]) {
+        let table = |deps: &[&str]| deps.iter()
+            .map(|dep| format!("{} = {{ path = \"../{}\" }}\n", dep, dep))
+            .collect::<String>();
+        dir.write(&format!("{}/Cargo.toml", path), &format!("\
+[package]
+name = \"{}\"
+version = \"0.1.0\"
+
+[dependencies]
+{}
+[dev-dependencies]
+{}", path.rsplit('/').next().unwrap(), table(deps), table(dev_deps)));
+    }
+    
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect()
+    }
+    
+    #[test]
+    fn release_order_follows_deps() {
+        let dir = TestDir::new("release-order");
+        dir.write("Cargo.toml", "[workspace]\nmembers = [\"*\"]\n");
+        package(&dir, "a", &["c"], &[]);
+        package(&dir, "b", &[], &[]);
+        package(&dir, "c", &["d"], &[]);
+        package(&dir, "d", &[], &["a"]);
+        
+        let workspace = Workspace::open(dir.path()).unwrap();
+        assert_eq!(names(workspace.members()), vec!["a", "b", "c", "d"]);
+        // d's dev-dependency on a doesn't make a cycle, if dropped
+        assert_eq!(names(&workspace.release_order(|_| Ok(true)).unwrap()), 
+            vec!["b", "d", "c", "a"]);
+        assert!(workspace.release_order(|_| Ok(false)).is_err());
+    }
+    
+    #[test]
+    fn release_order_kept_dev_deps() {
+        let dir = TestDir::new("release-order-dev");
+        dir.write("Cargo.toml", "[workspace]\nmembers = [\"*\"]\n");
+        package(&dir, "a", &[], &["b"]);
+        package(&dir, "b", &[], &[]);
+        package(&dir, "c", &[], &[]);
+        // a dev-dependency with a version isn't dropped
+        dir.write("c/Cargo.toml", &(dir.read("c/Cargo.toml") 
+            + "a = { path = \"../a\", version = \"0.1\" }\n"));
+        
+        let workspace = Workspace::open(dir.path()).unwrap();
+        assert_eq!(names(&workspace.release_order(|_| Ok(true)).unwrap()), 
+            vec!["a", "b", "c"]);
+        assert_eq!(names(&workspace.release_order(|_| Ok(false)).unwrap()), 
+            vec!["b", "a", "c"]);
+    }
+    
+    #[test]
+    fn release_order_cycle() {
+        let dir = TestDir::new("release-order-cycle");
+        dir.write("Cargo.toml", "[workspace]\nmembers = [\"a\", \"b\", \"c\"]\n");
+        package(&dir, "a", &["b"], &[]);
+        package(&dir, "b", &["a"], &[]);
+        package(&dir, "c", &[], &[]);
+        
+        let error = Workspace::open(dir.path()).unwrap()
+            .release_order(|_| Ok(true)).unwrap_err().to_string();
+        assert!(error.contains("dependency cycle"));
+        assert!(error.contains("/a\"") && error.contains("/b\""));
+        assert!(!error.contains("/c\""));
+    }
+    
+    #[test]
+    fn members_glob_and_exclude() {
+        let dir = TestDir::new("members-glob");
+        dir.write("Cargo.toml", "\
+[workspace]
+members = [\"crates/*\", \"tools/t?\"]
+exclude = [\"crates/old\"]
+");
+        package(&dir, "crates/one", &[], &[]);
+        package(&dir, "crates/two", &[], &[]);
+        package(&dir, "crates/old", &[], &[]);
+        package(&dir, "tools/t1", &[], &[]);
+        package(&dir, "tools/t10", &[], &[]);
+        // not packages
+        dir.write("crates/notes/README.md", "");
+        dir.write("crates/file", "");
+        
+        let workspace = Workspace::open(dir.path()).unwrap();
+        assert_eq!(names(workspace.members()), vec!["one", "two", "t1"]);
+    }
+}
@@ -0,0 +1,355 @@
+//! Deciding what a release would do, before doing it.
+//!
+//! The plan is made by the same steps as a real release, up
+//! to the point where something would be changed, so that it
+//! can be shown and approved before publishing.
+
+use crate::{
+    MoistMeter,
+    util::{
+        cli::ResultExt,
+        display::Lines,
+        path::path_rebase,
+        json::Json,
+        date::today,
+        git,
+    },
+    maniflect::{ManifestFile, DepSource},
+    changelog::{read_changelog, VersionNote, NoteMatch},
+    config::Config,
+    bump::VersionSpec,
+    release::{
+        LocalRepo,
+        DepPlan,
+        DepAction,
+        inspect_repo,
+        create_scratch_repo,
+        resolve_version,
+        check_version_order,
+        plan_delocalize,
+        plan_dep_releases,
+        audit_manifest,
+        cargo_publish_command,
+        tag_command,
+    },
+    journal::JournalRelease,
+    leet::log_indent,
+};
+use std::{
+    path::{Path, PathBuf},
+    collections::HashMap,
+    str::FromStr,
+    fmt::{self, Display, Formatter},
+};
+use semver::Version;
+use failure::{Error, format_err};
+
+/// Output format of a plan.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PlanFormat {
+    Text,
+    Json,
+}
+
+/// Everything a release of a package would do.
+pub struct Plan {
+    /// Local repo the release is made from.
+    pub repo: LocalRepo,
+    /// Scratch repo the release is made in.
+    pub srp: PathBuf,
+    pub config: Config,
+    /// Package directory, within the scratch repo.
+    pub package_path: PathBuf,
+    pub package_name: String,
+    /// Version in the manifest before the release.
+    pub current_version: String,
+    pub spec: Option<VersionSpec>,
+    /// Version to release, if one was specified.
+    pub version: Option<Version>,
+    pub note: Option<VersionNote>,
+    /// Whether the note is the unreleased changelog entry, 
+    /// which would be promoted to the version.
+    pub promote_unreleased: bool,
+    pub tag: Option<String>,
+    pub registry: Option<String>,
+    /// Local dependencies to release first, in order.
+    pub dep_releases: Vec<JournalRelease>,
+    pub deps: Vec<DepPlan>,
+    /// Commands which would be run, in order.
+    pub commands: Vec<String>,
+}
+
+impl Plan {
+    /// Decide everything about a release of a package,
+    /// changing nothing but the scratch repo.
+    ///
+    /// If `release_deps`, local dependencies which changed
+    /// since their latest release are planned to be released
+    /// first, within the same scratch repo.
+    ///
+    /// The version must be newer than the existing releases,
+    /// or if `allow_lower`, than those in its maintenance line.
+    ///
+    /// Dies if the release could not be made, such as when
+    /// the version isn't in the changelog.
+    pub fn build(
+        pckg: &Path,
+        spec: Option<VersionSpec>,
+        moist: MoistMeter,
+        registry: Option<&str>,
+        release_deps: bool,
+        allow_lower: bool,
+    ) -> Self {
+        let config = Config::load(
+            git::toplevel(pckg),
+            Some(&ManifestFile::new(pckg.join("Cargo.toml")).ekill()),
+        ).ekill();
+        let repo = inspect_repo(pckg, &config, moist);
+        let srp = create_scratch_repo(&repo, &config, moist, spec.is_some());
+
+        let package_path = path_rebase(pckg, &repo.path, &srp)
+            .ekill();
+        let manifest_file = ManifestFile::new(package_path.join("Cargo.toml"))
+            .ekill();
+        let package_name = manifest_file.name().ekill();
+        let current_version = manifest_file.version().ekill();
+        if spec.is_some() && audit_manifest(&manifest_file, registry) > 0 {
+            kill!("Manifest can't be published, so not releasing");
+        }
+        let version = spec.as_ref()
+            .map(|spec| resolve_version(&srp, &config, &manifest_file, spec));
+        if let Some(version) = version.as_ref() {
+            check_version_order(&srp, &config, &package_name, version, allow_lower)
+                .map_err(|e| kill!("{}", e))
+                .unwrap();
+        }
+        let dep_releases = match release_deps {
+            true => {
+                info!("Looking for changed local dependencies");
+                let indent = log_indent();
+                let dep_releases = plan_dep_releases(&srp, &package_path, registry);
+                indent.end();
+                dep_releases
+            },
+            false => Vec::new(),
+        };
+        let released = dep_releases.iter()
+            .map(|release| (release.name.clone(), release.version.clone()))
+            .collect::<HashMap<String, Version>>();
+        let deps = plan_delocalize(
+            &srp, &package_path, &released, registry, moist);
+
+        let changelog_path = config.changelog_path(&srp, &package_path, &package_name);
+        info!("Reading changelog at {:?}", changelog_path);
+        let changelog = read_changelog(
+            &changelog_path, config.changelog_section(&package_name))
+            .map_err(|e| kill!("error reading changelog:\n{}", e))
+            .unwrap();
+        debug!("Changelog: \n\n{}", Lines(&changelog.versions));
+        if let Some(unreleased) = changelog.unreleased.as_ref() {
+            debug!("Unreleased: \n\n{}", unreleased);
+        }
+
+        let mut promote = false;
+        let note = version.as_ref().map(|version| {
+            let found = changelog.find_notes(version, config.prerelease_notes);
+            let note = match (found, changelog.unreleased.as_ref()) {
+                (Some(note), _) => note,
+                (None, Some(unreleased)) if unreleased.body.trim().is_empty() => kill!(
+                    "Could not find version {} in changelog, and the unreleased \
+                    entry is empty", version),
+                (None, Some(unreleased)) => {
+                    info!("Promoting unreleased changes in changelog to {}", version);
+                    promote = true;
+                    VersionNote {
+                        version: version.clone(),
+                        date: Some(today()).filter(|_| config.changelog_date),
+                        yanked: false,
+                        body: unreleased.body.clone(),
+                        span: unreleased.span.clone(),
+                        matched: NoteMatch::Exact,
+                    }
+                },
+                (None, None) => kill!(
+                    "Could not find version {} in changelog", version),
+            };
+            info!("Package name = {}", package_name);
+            info!("Current version = {}", current_version);
+            match note.matched {
+                NoteMatch::Exact => info!("Found version {} in changelog:\n{}", version, note),
+                matched => info!("Found version {} in changelog, by {}:\n{}", 
+                    version, matched, note),
+            };
+            note
+        });
+        let tag = version.as_ref()
+            .map(|version| config.release_tag(&package_name, version));
+
+        // the commands run by a release
+        let mut commands = Vec::new();
+        let mut tags = Vec::new();
+        for release in &dep_releases {
+            let dep_config = Config::load(
+                &srp, Some(&ManifestFile::new(release.path.join("Cargo.toml")).ekill()),
+            ).ekill();
+            let dep_tag = dep_config.release_tag(&release.name, &release.version);
+            commands.extend(dep_config.checks.iter().cloned());
+            push_release_commands(&mut commands, &dep_tag, registry, moist);
+            tags.push(dep_tag);
+        }
+        commands.extend(config.checks.iter().cloned());
+        if let Some(tag) = tag.as_ref() {
+            push_release_commands(&mut commands, tag, registry, moist);
+            tags.push(tag.clone());
+        }
+        if moist == MoistMeter::Wet && !tags.is_empty() {
+            commands.push(format!("git push -u {0} {1}:{1}", config.remote, repo.branch));
+            for tag in &tags {
+                commands.push(format!("git push -u {0} {1}:{1}", config.remote, tag));
+            }
+        }
+
+        Plan {
+            repo,
+            srp,
+            config,
+            package_path,
+            package_name,
+            current_version,
+            spec,
+            version,
+            note,
+            promote_unreleased: promote,
+            tag,
+            registry: registry.map(String::from),
+            dep_releases,
+            deps,
+            commands,
+        }
+    }
+
+    /// Convert to JSON, for release bots.
+    pub fn to_json(&self) -> Json {
+        let local_path = |path: &Path| path_rebase(path, &self.srp, &self.repo.path)
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|_| path.display().to_string());
+        let bump = match self.spec {
+            Some(VersionSpec::Bump(bump)) => Some(bump.to_string()),
+            _ => None,
+        };
+
+        Json::object(vec![
+            ("package", self.package_name.as_str().into()),
+            ("path", local_path(&self.package_path).into()),
+            ("branch", self.config.branch.as_str().into()),
+            ("remote", self.config.remote.as_str().into()),
+            ("registry", self.registry.clone().into()),
+            ("current_version", self.current_version.as_str().into()),
+            ("bump", bump.into()),
+            ("version", self.version.as_ref().map(Version::to_string).into()),
+            ("tag", self.tag.clone().into()),
+            ("changelog_note", self.note.as_ref().map(|note| note.body.clone()).into()),
+            ("changelog_match", self.note.as_ref()
+                .map(|note| note.matched.to_string()).into()),
+            ("changelog_promoted", self.promote_unreleased.into()),
+            ("dependency_releases", Json::Array(self.dep_releases.iter()
+                .map(|release| Json::object(vec![
+                    ("package", release.name.as_str().into()),
+                    ("path", local_path(&release.path).into()),
+                    ("version", release.version.to_string().into()),
+                ]))
+                .collect())),
+            ("dependencies", Json::Array(self.deps.iter()
+                .map(|dep| Json::object(vec![
+                    ("package", dep.package.as_str().into()),
+                    ("table", dep.key.table().to_string().into()),
+                    ("path", local_path(&dep.path).into()),
+                    ("action", match dep.action {
+                        DepAction::Delocalize(_) => "delocalize",
+                        DepAction::KeepLocal => "keep-local",
+                        DepAction::Drop => "drop",
+                    }.into()),
+                    ("version_req", dep.action.source()
+                        .and_then(DepSource::crate_version).into()),
+                    ("registry", dep.action.source()
+                        .and_then(DepSource::registry).into()),
+                ]))
+                .collect())),
+            ("commands", self.commands.clone().into()),
+        ])
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "package: {} {}", self.package_name, self.current_version)?;
+        writeln!(f, "branch: {}", self.config.branch)?;
+        writeln!(f, "remote: {}", self.config.remote)?;
+        writeln!(f, "registry: {}", self.registry.as_deref().unwrap_or("crates.io"))?;
+        if let Some(version) = self.version.as_ref() {
+            match self.spec {
+                Some(VersionSpec::Bump(bump)) => writeln!(f, "version: {} ({})", version, bump)?,
+                _ => writeln!(f, "version: {}", version)?,
+            };
+        }
+        if let Some(tag) = self.tag.as_ref() {
+            writeln!(f, "tag: {}", tag)?;
+        }
+        if self.promote_unreleased {
+            writeln!(f, "changelog: unreleased entry promoted")?;
+        }
+        if let Some(note) = self.note.as_ref().filter(|note| note.matched != NoteMatch::Exact) {
+            writeln!(f, "changelog: notes of {}, by {}", note.version, note.matched)?;
+        }
+        for release in &self.dep_releases {
+            writeln!(f, "release first: {} {}", release.name, release.version)?;
+        }
+        for dep in &self.deps {
+            write!(f, "{}: {} ", dep.key.table(), dep.package)?;
+            match dep.action {
+                DepAction::Delocalize(ref source) => writeln!(f, "-> {}{}",
+                    source.crate_version().unwrap_or(""),
+                    source.registry()
+                        .map(|registry| format!(" from {}", registry))
+                        .unwrap_or_default())?,
+                DepAction::KeepLocal => writeln!(f, "kept local")?,
+                DepAction::Drop => writeln!(f, "dropped")?,
+            };
+        }
+        for command in &self.commands {
+            writeln!(f, "command: {}", command)?;
+        }
+        if let Some(note) = self.note.as_ref() {
+            write!(f, "\n{}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Add the commands which release a package, after its 
+/// checks, except for pushing.
+fn push_release_commands(
+    commands: &mut Vec<String>,
+    tag: &str,
+    registry: Option<&str>,
+    moist: MoistMeter,
+) {
+    commands.push(format!(r#"git commit -m "Publish {}""#, tag));
+    commands.push(tag_command(tag));
+    commands.push(cargo_publish_command(registry, moist));
+    if moist == MoistMeter::Wet {
+        commands.push(format!(r#"git commit -m "After-release {}""#, tag));
+    }
+}
+
+impl FromStr for PlanFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "text" => Ok(PlanFormat::Text),
+            "json" => Ok(PlanFormat::Json),
+            _ => Err(format_err!("invalid plan format {:?}", s)),
+        }
+    }
+}
@@ -0,0 +1,1215 @@
+//! Steps of the release process, shared between subcommands.
+
+use crate::{
+    MoistMeter,
+    config::{Config, DevDepPolicy},
+    bump::{Bump, VersionSpec, bump_base},
+    util::{
+        hex::Hex,
+        cli::ResultExt,
+        display::{
+            Lines,
+            LinesView,
+        },
+        cmd::{
+            preadln,
+            preadlns,
+            pnonempty,
+            exec_stderr,
+        },
+        git::{self, Commit},
+        date::today,
+    },
+    maniflect::{ManifestFile, DepSource, DepKey, DepKind},
+    journal::{Journal, JournalRelease},
+    changelog::{read_changelog, promote_unreleased, plain_text, VersionNote},
+    draft::{Change, read_changes, suggest_bump},
+    scratch::{tmp_dir, SCRATCH_PREFIX},
+    leet::{
+        catch_errors,
+        log_indent,
+        on_failure,
+        CatchErrors,
+    },
+};
+use std::{
+    path::{PathBuf, Path},
+    fs::{
+        self,
+        canonicalize,
+        create_dir as mkdir,
+    },
+    collections::{HashMap, BTreeSet},
+};
+use rand::prelude::*;
+use semver::{
+    Version,
+    VersionReq
+};
+use failure::{Error, format_err};
+
+/// The local repo which a release is made from.
+pub struct LocalRepo {
+    /// Root of the repo.
+    pub path: String,
+    /// Currently checked out branch.
+    pub branch: String,
+}
+
+/// Find the local repo containing a package, and check
+/// that it's in a state to be released from.
+pub fn inspect_repo(pckg: &Path, config: &Config, moist: MoistMeter) -> LocalRepo {
+    let pckg_repo = git::toplevel(pckg);
+    debug!("Using the repo at:\n{:?}", pckg_repo);
+
+    let pckg_branch = exec!(
+        [&pckg, "git rev-parse --abbrev-ref HEAD"]
+        | (preadln)
+    );
+    debug!("Which is in branch {:?}", pckg_branch);
+    if pckg_branch != config.branch {
+        match moist {
+            MoistMeter::Dry => warn!("Repo is not in {} branch", config.branch),
+            MoistMeter::Wet => kill!("Repo is not in {} branch", config.branch),
+        };
+    }
+    if exec!(
+        [&pckg_repo, "git log {}/{}..HEAD", config.remote, pckg_branch] | (pnonempty)
+    ) {
+        match moist {
+            MoistMeter::Dry => warn!("Repo has unpushed commits"),
+            MoistMeter::Wet => kill!("Repo has unpushed commits"),
+        };
+    }
+    if exec!(
+        [&pckg_repo, "git log HEAD..{}/{}", config.remote, pckg_branch] | (pnonempty)
+    ) {
+        match moist {
+            MoistMeter::Dry => warn!("Repo is behind {}", config.remote),
+            MoistMeter::Wet => kill!("Repo is behind {}", config.remote),
+        };
+    }
+
+    LocalRepo {
+        path: pckg_repo,
+        branch: pckg_branch,
+    }
+}
+
+/// Create a scratch repo to make the release in, and return
+/// its path.
+///
+/// When dry, local changes are copied over, and committed if
+/// `commit_local_changes`. When wet, local changes are an
+/// error, and the content comes from the remote.
+///
+/// If the process fails, the scratch repo is kept, and its
+/// path is logged.
+pub fn create_scratch_repo(
+    repo: &LocalRepo,
+    config: &Config,
+    moist: MoistMeter,
+    commit_local_changes: bool,
+) -> PathBuf {
+    let pckg_repo = &repo.path;
+    let pckg_branch = &repo.branch;
+
+    let tmp = tmp_dir();
+    debug!("Using temp directory:\n{:?}", &tmp);
+    let srp: PathBuf = tmp.join(format!("{}{}", SCRATCH_PREFIX, random::<Hex>()));
+    debug!("Creating scratch repo in:\n{:?}", srp);
+
+    mkdir(&srp).ekill();
+    on_failure(format!("Scratch repo kept at:\n{:?}", srp));
+    exec!([&srp, "git init"]);
+    match moist {
+        MoistMeter::Dry => {
+            // pull from local, and move over local changes
+            exec!([&srp, "git remote add local {:?}", pckg_repo]);
+            exec!([&srp, "git fetch local"]);
+            exec!([&srp, "git -c advice.detachedHead=false checkout local/{}", pckg_branch]);
+
+            let mut local_changes = false;
+            if exec!([&pckg_repo, "git diff"] | (pnonempty)) {
+                exec!([&pckg_repo, "git diff"] | [&srp, "git apply"]);
+                local_changes = true;
+            }
+            for path in exec!(
+                [&pckg_repo, "git ls-files --others --exclude-standard"]
+                | (preadlns))
+            {
+                fs::create_dir_all(srp.join(&path).parent().unwrap()).ekill();
+                fs::copy(
+                    Path::new(&pckg_repo).join(&path),
+                    srp.join(&path)
+                ).ekill();
+                local_changes = true;
+            }
+            if local_changes {
+                warn!("Uncommitted local changes copied over.");
+                if commit_local_changes {
+                    trace!("Creating commit for copied over local changes");
+                    exec!([&srp, "git add ."]);
+                    exec!([&srp, r#"git commit -m "(local changes copied over by DEET)""#])
+                }
+            }
+        },
+        MoistMeter::Wet => {
+            // stopgap
+            if exec!(
+                [&pckg_repo, "git diff"] | (pnonempty)
+            ) {
+                kill!("There are uncommitted local changes:\n{}",
+                    Lines(exec!([&pckg_repo, "git diff"] | (preadlns))));
+            }
+
+            if exec!(
+                [&pckg_repo, "git ls-files --others --exclude-standard"] | (pnonempty)
+            ) {
+                kill!("There are uncommitted local new files:\n{}",
+                    Lines(exec!([&pckg_repo, "git ls-files --others --exclude-standard"] | (preadlns))));
+            }
+
+            let remote = &config.remote;
+            let origin = exec!([&pckg_repo, "git config --get remote.{}.url", remote] | (preadln));
+            info!("Pulling from {}", origin);
+            exec!([&srp, "git remote add {} {:?}", remote, origin]);
+            exec!([&srp, "git fetch {}", remote]);
+            exec!([&srp, "git checkout {}/{}", remote, pckg_branch]);
+        },
+    };
+
+    srp
+}
+
+/// The most recent release of a local package.
+#[derive(Debug, Clone)]
+pub struct FoundRelease {
+    pub version: Version,
+    /// Commit the release is tagged on.
+    pub commit: Commit,
+    /// Commits since the release which effect the package,
+    /// not counting its after-release commit.
+    pub unreleased: Vec<Commit>,
+}
+
+/// Find the most recent release of a local package, by 
+/// walking back through the commits which effect it until 
+/// one is tagged with a release.
+pub fn find_release(
+    srp: &Path,
+    config: &Config,
+    local_path: &Path,
+    package: &str,
+) -> Result<FoundRelease, Error> {
+    let commits = git::follow(srp, local_path);
+    debug!("Found relevant commits:\n{}",
+        LinesView(&commits, |c| &c.pretty));
+
+    for (i, commit) in commits.iter().enumerate() {
+        let tags: Vec<String> = exec!(
+            [&srp, "git tag --points-at {}", commit.hash]
+            | (preadlns));
+        let mut versions: Vec<Version> = tags.iter()
+            .filter_map(|tag| config.parse_release_tag(tag, package))
+            .collect();
+        versions.sort();
+        versions.dedup();
+        let version = match versions.as_slice() {
+            [] => continue,
+            [version] => version.clone(),
+            _ => return Err(format_err!("several versions found on commit {}:\n{}", 
+                commit.concise, Lines(&versions))),
+        };
+        info!("Found release {} on commit: {}", version, commit.concise);
+
+        let after_release: Vec<String> = config.release_tags(package, &version)
+            .iter()
+            .map(|tag| format!("After-release {}", tag))
+            .collect();
+        let unreleased = commits[..i].iter()
+            .filter(|c| !after_release.contains(&exec!(
+                [&srp, r#"git log --format="%s" -n 1 {}"#, c.hash]
+                | (preadln))))
+            .cloned()
+            .collect();
+        return Ok(FoundRelease {
+            version,
+            commit: commit.clone(),
+            unreleased,
+        });
+    }
+    Err(format_err!("no release of {} found in its history", package))
+}
+
+/// Find all releases of a package, among all tags in a repo,
+/// oldest first.
+pub fn releases(repo: &Path, config: &Config, package: &str) -> Vec<Version> {
+    let mut versions: Vec<Version> = exec!([&repo, "git tag -l"] | (preadlns))
+        .iter()
+        .filter_map(|tag| config.parse_release_tag(tag, package))
+        .collect();
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
+/// Find the newest release of a package, among all tags in
+/// a repo.
+pub fn newest_release(repo: &Path, config: &Config, package: &str) -> Option<Version> {
+    releases(repo, config, package).pop()
+}
+
+/// Check that a version of a package may be released, given
+/// its existing releases, before anything is changed.
+///
+/// A version which was already released is refused, and so is
+/// one lower than the newest release, unless `allow_lower`, for
+/// maintenance lines, in which case it must still be newer 
+/// than the releases it's semver compatible with. Skipping 
+/// versions after the previous release is reported according
+/// to the config.
+pub fn check_version_order(
+    repo: &Path,
+    config: &Config,
+    package: &str,
+    version: &Version,
+    allow_lower: bool,
+) -> Result<(), Error> {
+    let releases = releases(repo, config, package);
+    debug!("Existing releases of {}:\n{}", package, Lines(&releases));
+    
+    if let Some(prev) = order_against(&releases, package, version, allow_lower)? {
+        config.skipped_versions.report(format_args!(
+            "{} {} skips versions after the previous release {}", 
+            package, version, prev));
+    }
+    Ok(())
+}
+
+/// Check a version against a package's existing releases, as
+/// `check_version_order` does, and return the previous release
+/// if the version skips some after it.
+fn order_against<'a>(
+    releases: &'a [Version],
+    package: &str,
+    version: &Version,
+    allow_lower: bool,
+) -> Result<Option<&'a Version>, Error> {
+    if releases.contains(version) {
+        return Err(format_err!("{} {} was already released", package, version));
+    }
+    let newest = releases.iter()
+        .filter(|release| !allow_lower || compatible(release, version))
+        .max();
+    if let Some(newest) = newest.filter(|&newest| newest > version) {
+        return Err(match allow_lower {
+            false => format_err!("{} {} is lower than the newest release {}, \
+                pass --allow-lower to release it for a maintenance line",
+                package, version, newest),
+            true => format_err!("{} {} is lower than the release {}, which is \
+                in the same maintenance line", package, version, newest),
+        });
+    }
+    
+    // the previous release, and the versions which follow it
+    let prev = match releases.iter().filter(|&release| release < version).max() {
+        Some(prev) => prev,
+        None => return Ok(None),
+    };
+    let mut base = version.clone();
+    base.pre.clear();
+    base.build.clear();
+    let next = [Bump::Patch, Bump::Minor, Bump::Major, Bump::Pre].iter()
+        .map(|bump| bump.apply(prev))
+        .collect::<Vec<Version>>();
+    match next.contains(version) || next.contains(&base) {
+        true => Ok(None),
+        false => Ok(Some(prev)),
+    }
+}
+
+/// Whether two versions are semver compatible, so are in the 
+/// same maintenance line.
+fn compatible(a: &Version, b: &Version) -> bool {
+    match (a.major, a.minor) {
+        _ if a.major != b.major => false,
+        (0, _) if a.minor != b.minor => false,
+        (0, 0) => a.patch == b.patch,
+        _ => true,
+    }
+}
+
+/// Resolve the version to release.
+///
+/// For bump keywords, the newest release tag must agree with
+/// the manifest version, which should be that release with the
+/// after-release suffix, and the bump is applied to that.
+pub fn resolve_version(
+    srp: &Path,
+    config: &Config,
+    manifest_file: &ManifestFile,
+    spec: &VersionSpec,
+) -> Version {
+    let bump = match *spec {
+        VersionSpec::Exact(ref version) => return version.clone(),
+        VersionSpec::Bump(bump) => bump,
+    };
+    let package_name = manifest_file.name().ekill();
+    let manifest_version = manifest_file.version().ekill();
+
+    let tagged = newest_release(srp, config, &package_name);
+    debug!("Newest release tag = {:?}, manifest version = {:?}",
+        tagged.as_ref().map(Version::to_string), manifest_version);
+    let prev = bump_base(config, &package_name, tagged, &manifest_version)
+        .map_err(|e| kill!("{}", e))
+        .unwrap();
+
+    let version = bump.apply(&prev);
+    info!("Bumping {} from {} to {}", bump, prev, version);
+    version
+}
+
+/// What to do with a local path dependency.
+#[derive(Debug, Clone)]
+pub struct DepPlan {
+    pub key: DepKey,
+    pub package: String,
+    /// Canonicalized local path.
+    pub path: PathBuf,
+    pub action: DepAction,
+}
+
+/// What to do with a local path dependency.
+#[derive(Debug, Clone)]
+pub enum DepAction {
+    /// Replace the local path with this source.
+    Delocalize(DepSource),
+    /// Keep the local path, since the dependency was only
+    /// released in a dry run.
+    KeepLocal,
+    /// Leave a path-only dev-dependency, which `cargo publish`
+    /// drops.
+    Drop,
+}
+
+impl DepAction {
+    /// Get the source to replace the local path with, if
+    /// delocalized.
+    pub fn source(&self) -> Option<&DepSource> {
+        match self {
+            DepAction::Delocalize(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Decide how to replace the local path dependencies of a 
+/// package with version requirements on their releases, 
+/// without changing anything.
+///
+/// Each dependency's release tags are read with the config
+/// of that dependency.
+///
+/// Each dependency's version is its most recent release, 
+/// which is required by the strategy in the package's config,
+/// and changes to it since then are reported according to the 
+/// package's config. A dependency with both a path and a
+/// version keeps its version requirement, unless it doesn't
+/// match that release, which is reported as stale.
+///
+/// Every dependency table is covered. Dev-dependencies are
+/// dropped or delocalized according to the package's config.
+///
+/// Dependencies in `released` were released earlier in this
+/// same run. When wet, they're delocalized to that version.
+/// When dry, that version was never published, so they're
+/// kept local.
+///
+/// A dependency's `registry` key is kept. Otherwise, it's 
+/// taken from `registry` if the dependency was released in
+/// this run, or from the dependency's `publish` key if that
+/// names exactly one registry.
+///
+/// Dependencies whose version can't be determined are logged
+/// as errors and left out.
+pub fn plan_delocalize(
+    srp: &Path,
+    package_path: &Path,
+    released: &HashMap<String, Version>,
+    registry: Option<&str>,
+    moist: MoistMeter,
+) -> Vec<DepPlan> {
+    let manifest_path = package_path.join("Cargo.toml");
+    info!("Delocalizing manifest at:\n{:?}", manifest_path);
+
+    let indent = log_indent();
+    let mut plans = Vec::new();
+    let manifest_file = ManifestFile::new(&manifest_path).ekill();
+    let config = Config::load(srp, Some(&manifest_file)).ekill();
+    for dep in manifest_file.deps().ekill() {
+        // get and canonicalize the local path
+        let local_path = match dep.local_path() {
+            Some(path) => canonicalize(path).ekill(),
+            None => continue,
+        };
+
+        indent.linebreak();
+        info!("De-localizing {} {:?} at:\n{:?}", dep.table(), dep.package(), local_path);
+        
+        let path_only = dep.source().is_local();
+        if dep.table().kind == DepKind::Dev && path_only && config.dev_deps == DevDepPolicy::Drop {
+            info!("Leaving dev-dependency path-only, so cargo publish drops it");
+            plans.push(DepPlan {
+                key: dep.key(),
+                package: dep.package().to_owned(),
+                path: local_path,
+                action: DepAction::Drop,
+            });
+            continue;
+        }
+        let dep_manifest = ManifestFile::new(local_path.join("Cargo.toml")).ekill();
+
+        // select the version
+        let version = match (released.get(dep.package()), moist) {
+            (Some(_), MoistMeter::Dry) => {
+                info!("Keeping dependency local, since it was only \
+                    released in this dry run");
+                plans.push(DepPlan {
+                    key: dep.key(),
+                    package: dep.package().to_owned(),
+                    path: local_path,
+                    action: DepAction::KeepLocal,
+                });
+                continue;
+            },
+            (Some(v), MoistMeter::Wet) => {
+                info!("Dependency was released in this run");
+                v.clone()
+            },
+            (None, _) => {
+                let dep_config = Config::load(srp, Some(&dep_manifest)).ekill();
+                let found = match find_release(
+                    srp, &dep_config, &local_path, dep.package())
+                {
+                    Ok(found) => found,
+                    Err(e) => {
+                        error!("{}", e);
+                        continue;
+                    },
+                };
+                if !found.unreleased.is_empty() {
+                    config.unreleased_deps.report(format_args!(
+                        "Dependency has unreleased changes since {}:\n{}",
+                        dep_config.release_tag(dep.package(), &found.version),
+                        LinesView(&found.unreleased, |c| &c.pretty)));
+                }
+                found.version
+            },
+        };
+
+        let strategy = config.version_req(dep.package());
+        let version_req = match strategy.requirement(&version) {
+            Ok(version_req) => version_req,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            },
+        };
+        info!("Found version {}, requiring {} by {}", version, version_req, strategy);
+        
+        // keep a declared version requirement, unless stale
+        let version_req = match dep.source() {
+            DepSource::LocalWithVersion { version: declared, .. } => 
+                match VersionReq::parse(&declared) {
+                    Ok(req) if req.matches(&version) => {
+                        info!("Keeping declared version requirement {:?}", declared);
+                        declared
+                    },
+                    Ok(_) => {
+                        config.stale_deps.report(format_args!(
+                            "Dependency version requirement {:?} is stale, since \
+                            it doesn't match {}, so replacing it with {:?}",
+                            declared, version, version_req));
+                        version_req
+                    },
+                    Err(e) => {
+                        error!("Invalid dependency version requirement {:?}: {}", 
+                            declared, e);
+                        continue;
+                    },
+                },
+            _ => version_req,
+        };
+
+        // select the registry, defaulting to the one being published
+        // to, unless the dependency can't be published there
+        let dep_registry = dep.registry().map(String::from)
+            .or_else(|| match (registry, dep_manifest.publish_registries()) {
+                (Some(registry), None) => Some(registry.to_owned()),
+                (Some(registry), Some(registries))
+                    if registries.iter().any(|r| r == registry) =>
+                    Some(registry.to_owned()),
+                (_, Some(mut registries)) if registries.len() == 1 =>
+                    registries.pop(),
+                _ => None,
+            })
+            .filter(|registry| registry != "crates-io");
+
+        let source = match dep_registry {
+            Some(dep_registry) => {
+                debug!("Replacing local dep with version req {} from registry {}",
+                    version_req, dep_registry);
+                DepSource::Registry {
+                    version: version_req,
+                    registry: dep_registry,
+                }
+            },
+            None => {
+                debug!("Replacing local dep with version req {}", version_req);
+                DepSource::Crates {
+                    version: version_req,
+                }
+            },
+        };
+        plans.push(DepPlan {
+            key: dep.key(),
+            package: dep.package().to_owned(),
+            path: local_path,
+            action: DepAction::Delocalize(source),
+        });
+    }
+    indent.end();
+
+    plans
+}
+
+/// Find the local dependencies of a package, transitively,
+/// which changed since their latest release, and the versions
+/// to release them as. Each comes after its own dependencies,
+/// so they can be released in order.
+///
+/// The version released is decided as for workspace members,
+/// by `plan_changed_release`. Dev-dependencies which cargo
+/// publish drops are ignored, as in workspace release order.
+///
+/// Dependencies which can't be released, including to the
+/// registry, are logged as errors and left out.
+pub fn plan_dep_releases(
+    srp: &Path, 
+    package_path: &Path, 
+    registry: Option<&str>,
+) -> Vec<JournalRelease> {
+    let mut visiting = vec![package_path.to_owned()];
+    let mut visited = BTreeSet::new();
+    let mut releases = Vec::new();
+    visit_dep_releases(
+        srp, package_path, registry, &mut visiting, &mut visited, &mut releases);
+    releases
+}
+
+fn visit_dep_releases(
+    srp: &Path,
+    package_path: &Path,
+    registry: Option<&str>,
+    visiting: &mut Vec<PathBuf>,
+    visited: &mut BTreeSet<PathBuf>,
+    releases: &mut Vec<JournalRelease>,
+) {
+    let manifest_file = ManifestFile::new(package_path.join("Cargo.toml")).ekill();
+    let config = Config::load(srp, Some(&manifest_file)).ekill();
+    for dep in manifest_file.deps().ekill() {
+        // cargo publish drops these, so they needn't be released
+        if dep.table().kind == DepKind::Dev 
+            && dep.source().is_local() 
+            && config.dev_deps == DevDepPolicy::Drop 
+        {
+            continue;
+        }
+        let local_path = match dep.local_path() {
+            Some(path) => canonicalize(&path)
+                .map_err(|e| kill!("cannot find local dependency {:?} at {:?}:\n{}",
+                    dep.package(), path, e))
+                .unwrap(),
+            None => continue,
+        };
+        if visited.contains(&local_path) {
+            continue;
+        }
+        if visiting.contains(&local_path) {
+            error!("Dependency cycle through {:?} at:\n{:?}", dep.package(), local_path);
+            continue;
+        }
+
+        visiting.push(local_path.clone());
+        visit_dep_releases(srp, &local_path, registry, visiting, visited, releases);
+        visiting.pop();
+        visited.insert(local_path.clone());
+
+        info!("Dependency {:?} at:\n{:?}", dep.package(), local_path);
+        let indent = log_indent();
+        if let Some(release) = plan_dep_release(srp, &local_path, registry) {
+            releases.push(release);
+        }
+        indent.end();
+    }
+}
+
+/// Decide whether a local dependency needs to be released, 
+/// and at which version.
+fn plan_dep_release(
+    srp: &Path, 
+    local_path: &Path, 
+    registry: Option<&str>,
+) -> Option<JournalRelease> {
+    let manifest_file = ManifestFile::new(local_path.join("Cargo.toml")).ekill();
+    let name = manifest_file.name().ekill();
+    let config = Config::load(srp, Some(&manifest_file)).ekill();
+    
+    if !manifest_file.publishable() {
+        error!("Changes to {} can't be released, since it is not publishable", name);
+        return None;
+    }
+    plan_changed_release(srp, &config, &manifest_file, local_path, registry, false)
+}
+
+/// Decide whether a local package changed since its latest
+/// release, not counting its after-release commit, and if so,
+/// at which version to release it.
+///
+/// That's the newest version in its changelog, unless it was
+/// already released and the unreleased entry has notes. Then,
+/// the unreleased entry is promoted, to the latest release
+/// bumped as its commits suggest. Packages which changed, but
+/// can't be released, are logged as errors and left out.
+pub fn plan_changed_release(
+    srp: &Path,
+    config: &Config,
+    manifest_file: &ManifestFile,
+    package_path: &Path,
+    registry: Option<&str>,
+    allow_lower: bool,
+) -> Option<JournalRelease> {
+    let name = manifest_file.name().ekill();
+    let found = match find_release(srp, config, package_path, &name) {
+        Ok(found) => {
+            if found.unreleased.is_empty() {
+                info!("Unchanged since release {}", found.version);
+                return None;
+            }
+            Some(found)
+        },
+        Err(e) => {
+            info!("Changed, since: {}", e);
+            None
+        },
+    };
+    if audit_manifest(manifest_file, registry) > 0 {
+        error!("Changed, but the manifest can't be published");
+        return None;
+    }
+    
+    let changelog_path = config.changelog_path(srp, package_path, &name);
+    let changelog = read_changelog(&changelog_path, config.changelog_section(&name))
+        .map_err(|e| kill!("error reading changelog at {:?}:\n{}", 
+            changelog_path, e))
+        .unwrap();
+    let unreleased = changelog.unreleased.as_ref()
+        .filter(|unreleased| !unreleased.body.trim().is_empty());
+    let newest_released = changelog.newest()
+        .map(|note| releases(srp, config, &name).contains(&note.version))
+        .unwrap_or(true);
+    let (version, note) = match (changelog.newest(), unreleased, found) {
+        (Some(note), _, _) if !newest_released => (note.version.clone(), Some(note)),
+        (_, Some(_), Some(found)) => {
+            let changes: Vec<Change> = read_changes(srp, &found.unreleased)
+                .into_iter()
+                .map(|(_, change)| change)
+                .collect();
+            let bump = suggest_bump(&changes, Some(&found.version));
+            (bump.apply(&found.version), None)
+        },
+        (Some(note), _, _) => (note.version.clone(), Some(note)),
+        (None, _, _) => {
+            error!("Changed, but there are no versions in changelog");
+            return None;
+        },
+    };
+    if let Err(e) = check_version_order(srp, config, &name, &version, allow_lower) {
+        error!("Changed, but can't release newest version in changelog: {}", e);
+        return None;
+    }
+    
+    match note {
+        Some(note) => info!("Found version {} in changelog:\n{}", version, note),
+        None => info!("Promoting unreleased changes in changelog to {}", version),
+    };
+    Some(JournalRelease {
+        path: package_path.to_owned(),
+        name,
+        version,
+        promote_unreleased: note.is_none(),
+    })
+}
+
+/// Replace the local path dependencies of a package as
+/// planned, and save its manifest. Returns whether any
+/// dependencies were kept local.
+pub fn apply_delocalize(package_path: &Path, plans: &[DepPlan]) -> (ManifestFile, bool) {
+    let mut manifest_file = ManifestFile::new(package_path.join("Cargo.toml")).ekill();
+    let mut kept_local = false;
+    for plan in plans {
+        match plan.action {
+            DepAction::Delocalize(ref source) => manifest_file.dep(&plan.key).ekill()
+                .set_source(source.clone()),
+            DepAction::KeepLocal => kept_local = true,
+            DepAction::Drop => (),
+        };
+    }
+    manifest_file.save().ekill();
+
+    (manifest_file, kept_local)
+}
+
+/// Replace the local path dependencies of a package with
+/// version requirements on their releases, and save its 
+/// manifest. Returns whether any dependencies were kept local.
+pub fn delocalize(
+    srp: &Path,
+    package_path: &Path,
+    released: &HashMap<String, Version>,
+    registry: Option<&str>,
+    moist: MoistMeter,
+) -> (ManifestFile, bool) {
+    let plans = plan_delocalize(srp, package_path, released, registry, moist);
+    apply_delocalize(package_path, &plans)
+}
+
+/// Audit whether a package's manifest can be published, 
+/// before building anything, logging each problem as an 
+/// error. Returns the number of problems.
+pub fn audit_manifest(manifest_file: &ManifestFile, registry: Option<&str>) -> usize {
+    info!("Auditing manifest at {:?}", manifest_file.path());
+    let problems = manifest_file.audit(registry);
+    let indent = log_indent();
+    for problem in &problems {
+        error!("{}", problem);
+    }
+    indent.end();
+    problems.len()
+}
+
+/// Lint a package's changelog against its release tags in a 
+/// repo, logging each problem as a warning. Returns the 
+/// number of problems.
+pub fn lint_changelog(repo: &Path, config: &Config, package_path: &Path) -> usize {
+    let manifest_file = ManifestFile::new(package_path.join("Cargo.toml")).ekill();
+    let package_name = manifest_file.name().ekill();
+    let changelog_path = config.changelog_path(repo, package_path, &package_name);
+    info!("Linting changelog at {:?}", changelog_path);
+    let changelog = read_changelog(
+        &changelog_path, config.changelog_section(&package_name))
+        .map_err(|e| kill!("error reading changelog at {:?}:\n{}", 
+            changelog_path, e))
+        .unwrap();
+    
+    let problems = changelog.lint(
+        &releases(repo, config, &package_name), config.prerelease_notes);
+    let indent = log_indent();
+    for problem in &problems {
+        warn!("{}", problem);
+    }
+    indent.end();
+    problems.len()
+}
+
+/// Lint a package's changelog as a stage of its checks. The 
+/// problems are only warnings, which never fail a release.
+pub fn lint_changelog_stage(repo: &Path, config: &Config, package_path: &Path) {
+    let catch = catch_errors(false);
+    lint_changelog(repo, config, package_path);
+    catch.get();
+}
+
+/// Run the configured checks on a package.
+pub fn run_checks(package_path: &Path, config: &Config) {
+    for check in &config.checks {
+        info!("Running {}", check);
+        exec!([&package_path, "{}", check]);
+    }
+}
+
+/// Delocalize and check a package, and lint its changelog,
+/// before its release commit. Returns whether any 
+/// dependencies were kept local.
+pub fn prepare_release(
+    srp: &Path,
+    release: &JournalRelease,
+    released: &HashMap<String, Version>,
+    registry: Option<&str>,
+    moist: MoistMeter,
+) -> bool {
+    let manifest_file = ManifestFile::new(release.path.join("Cargo.toml")).ekill();
+    let config = Config::load(srp, Some(&manifest_file)).ekill();
+    let (_, kept_local) = delocalize(srp, &release.path, released, registry, moist);
+    run_checks(&release.path, &config);
+    lint_changelog_stage(srp, &config, &release.path);
+    kept_local
+}
+
+/// Make the releases in a journal, skipping steps which it
+/// records as already done. Returns the release tags.
+///
+/// Each package is committed with its release version, and 
+/// its unreleased changelog entry if it's promoted, then 
+/// tagged with its changelog note, and published. If there's
+/// a `notes_dir`, the note is also written there. When wet, 
+/// its manifest is then bumped to after the release, and once
+/// all are published, the commits and tags are pushed. If 
+/// configured, the after-release commit also puts local 
+/// dependency paths back, with the versions they were 
+/// published with.
+///
+/// `prepare` is called before each package's release commit,
+/// and returns whether any of its dependencies were kept 
+/// local. When wet, errors caught so far abort the process 
+/// before each release commit. A release which was committed,
+/// but not published, is checked again when resumed.
+pub fn execute_releases<F>(
+    journal: &mut Journal,
+    catch: &CatchErrors,
+    moist: MoistMeter,
+    notes_dir: Option<&Path>,
+    mut prepare: F,
+) -> Vec<String>
+where
+    F: FnMut(&JournalRelease, &HashMap<String, Version>) -> bool
+{
+    let srp = journal.srp.clone();
+    let registry = journal.registry.clone();
+    let mut released: HashMap<String, Version> = HashMap::new();
+    let mut publish_tags: Vec<String> = Vec::new();
+    
+    for release in journal.releases.clone() {
+        let manifest_path = release.path.join("Cargo.toml");
+        let config = Config::load(
+            &srp, Some(&ManifestFile::new(&manifest_path).ekill())).ekill();
+        let publish_tag = config.release_tag(&release.name, &release.version);
+        info!("Releasing {} {}", release.name, release.version);
+        let indent = log_indent();
+        
+        let commit_step = format!("commit {}", publish_tag);
+        let publish_step = format!("publish {}", publish_tag);
+        let kept_local = match journal.is_done(&commit_step) {
+            false => prepare(&release, &released),
+            // resumed after the release commit
+            true => {
+                if moist == MoistMeter::Wet && !journal.is_done(&publish_step) {
+                    info!("Checking again, since it wasn't published yet");
+                    let manifest_file = ManifestFile::new(&manifest_path).ekill();
+                    audit_manifest(&manifest_file, registry.as_deref());
+                    run_checks(&release.path, &config);
+                    lint_changelog_stage(&srp, &config, &release.path);
+                }
+                false
+            },
+        };
+        if moist == MoistMeter::Wet {
+            catch.checkpoint(false);
+        }
+        
+        // make a new commit
+        journal.step(&commit_step, || {
+            if head_subject(&srp) == format!("Publish {}", publish_tag) {
+                info!("Already committed {}", publish_tag);
+                return;
+            }
+            if release.promote_unreleased {
+                let changelog_path = config.changelog_path(
+                    &srp, &release.path, &release.name);
+                let date = match config.changelog_date {
+                    true => Some(today()),
+                    false => None,
+                };
+                let section = config.changelog_section(&release.name);
+                if promote_unreleased(
+                    &changelog_path, section, &release.version, date.as_deref()).ekill()
+                {
+                    info!("Promoted unreleased changes in changelog to {}", release.version);
+                }
+                exec!([&srp, "git add {:?}", changelog_path]);
+            }
+            
+            let mut manifest_file = ManifestFile::new(&manifest_path).ekill();
+            debug!("Altering version in manifest at:\n{:?}", manifest_path);
+            manifest_file.set_version(&release.version.to_string()).ekill();
+            manifest_file.save().ekill();
+            
+            info!("Creating new commit and tagging {}", publish_tag);
+            git_add_manifest(&srp, &manifest_file);
+            exec!([&srp, r#"git commit -m "Publish {}""#, publish_tag]);
+        });
+        let note = release_note(&srp, &release, &config);
+        journal.step(&format!("tag {}", publish_tag), || {
+            if tag_exists(&srp, &publish_tag) {
+                info!("Already tagged {}", publish_tag);
+                return;
+            }
+            let message = format!("{} {}\n\n{}", 
+                release.name, release.version, plain_text(&note.body));
+            fs::write(srp.join(TAG_MESSAGE), message).ekill();
+            exec!([&srp, "{}", tag_command(&publish_tag)]);
+        });
+        if let Some(notes_dir) = notes_dir {
+            write_release_notes(notes_dir, &publish_tag, &note);
+        }
+        
+        match moist {
+            MoistMeter::Dry => {
+                if kept_local {
+                    info!("Skipping cargo publish dry run, since dependencies \
+                        were kept local");
+                } else {
+                    cargo_publish(&release.path, registry.as_deref(), moist);
+                }
+            },
+            MoistMeter::Wet => {
+                journal.step(&publish_step, || {
+                    cargo_publish(&release.path, registry.as_deref(), moist);
+                    color!(green "[ INFO  ] Successfully published {}.";, publish_tag);
+                });
+                journal.step(&format!("after-release {}", publish_tag), || {
+                    if head_subject(&srp) == format!("After-release {}", publish_tag) {
+                        info!("Already bumped to after {}", publish_tag);
+                        return;
+                    }
+                    if config.persist_dep_versions {
+                        persist_dep_versions(&srp, &manifest_path, &publish_tag);
+                    }
+                    let mut manifest_file = ManifestFile::new(&manifest_path).ekill();
+                    manifest_file.set_version(
+                        &config.after_release_version(&release.version)).ekill();
+                    manifest_file.save().ekill();
+                    git_add_manifest(&srp, &manifest_file);
+                    exec!([&srp, r#"git commit -m "After-release {}""#, publish_tag]);
+                });
+            },
+        };
+        
+        indent.end();
+        released.insert(release.name.clone(), release.version.clone());
+        publish_tags.push(publish_tag);
+    }
+    
+    if moist == MoistMeter::Wet {
+        // push the scratch repo's commits and tags to the 
+        // remote, then pull them into the local repo
+        info!("Committing and pushing");
+        let pckg_repo = journal.repo.clone();
+        let pckg_branch = journal.branch.clone();
+        let remote = journal.remote.clone();
+        
+        journal.step("branch", || {
+            exec!([&srp, "git checkout -b {}", pckg_branch]);
+        });
+        journal.step("push-branch", || {
+            exec!([&srp, "git push -u {0} {1}:{1}", remote, pckg_branch]);
+        });
+        for publish_tag in &publish_tags {
+            journal.step(&format!("push-tag {}", publish_tag), || {
+                exec!([&srp, "git push -u {0} {1}:{1}", remote, publish_tag]);
+            });
+        }
+        journal.step("pull", || {
+            exec!([&pckg_repo, "git fetch {}", remote]);
+            exec!([&pckg_repo, "git pull {} {}", remote, pckg_branch]);
+            for publish_tag in &publish_tags {
+                exec!([&pckg_repo, "git pull {} {}", remote, publish_tag]);
+            }
+        });
+    }
+    journal.finish();
+    
+    publish_tags
+}
+
+/// Restore the local paths of dependencies which a release
+/// delocalized, from before its release commit, alongside
+/// the version requirements they were published with.
+fn persist_dep_versions(srp: &Path, manifest_path: &Path, publish_tag: &str) {
+    info!("Keeping published dependency versions alongside their paths");
+    let published = ManifestFile::new(manifest_path).ekill();
+    let requirements: HashMap<DepKey, String> = published.deps().ekill()
+        .filter_map(|dep| dep.source().crate_version()
+            .map(|version| (dep.key(), version.to_owned())))
+        .collect();
+    
+    exec!([&srp, "git checkout {}~1 -- {:?}", publish_tag, manifest_path]);
+    if let Some(root) = published.workspace_root() {
+        exec!([&srp, "git checkout {}~1 -- {:?}", publish_tag, root.path()]);
+    }
+    
+    let mut manifest_file = ManifestFile::new(manifest_path).ekill();
+    let restored: Vec<(DepKey, String)> = manifest_file.deps().ekill()
+        .filter_map(|dep| dep.source().local_path()
+            .map(|path| (dep.key(), path.to_owned())))
+        .collect();
+    for (key, path) in restored {
+        if let Some(version) = requirements.get(&key) {
+            debug!("Persisting {:?} = {:?} alongside path", key, version);
+            manifest_file.dep(&key).ekill()
+                .set_source(DepSource::LocalWithVersion {
+                    path,
+                    version: version.clone(),
+                });
+        }
+    }
+    manifest_file.save().ekill();
+}
+
+/// Path of the tag message file, within a scratch repo.
+const TAG_MESSAGE: &str = ".git/TAG_EDITMSG";
+
+/// The git command to tag a release, with the message in 
+/// `TAG_MESSAGE`, kept verbatim so that Markdown headers 
+/// aren't stripped as comments.
+pub fn tag_command(tag: &str) -> String {
+    format!("git tag -a {} -F {} --cleanup=verbatim HEAD", tag, TAG_MESSAGE)
+}
+
+/// Find the changelog note of a release, after its commit.
+fn release_note(srp: &Path, release: &JournalRelease, config: &Config) -> VersionNote {
+    let changelog_path = config.changelog_path(srp, &release.path, &release.name);
+    read_changelog(&changelog_path, config.changelog_section(&release.name))
+        .map_err(|e| kill!("error reading changelog at {:?}:\n{}", changelog_path, e))
+        .unwrap()
+        .find_notes(&release.version, config.prerelease_notes)
+        .ok_or_else(|| kill!("Could not find version {} in changelog", release.version))
+        .unwrap()
+}
+
+/// Write the changelog note of a release into a directory, as
+/// Markdown and as plain text, named after its tag.
+fn write_release_notes(dir: &Path, tag: &str, note: &VersionNote) {
+    let name = tag.replace('/', "-");
+    let md_path = dir.join(format!("{}.md", name));
+    let txt_path = dir.join(format!("{}.txt", name));
+    info!("Writing release notes to:\n{:?}\n{:?}", md_path, txt_path);
+    fs::create_dir_all(dir).ekill();
+    fs::write(&md_path, &note.body).ekill();
+    fs::write(&txt_path, plain_text(&note.body)).ekill();
+}
+
+/// Stage a manifest, and its workspace root, which it may
+/// have edited.
+fn git_add_manifest(srp: &Path, manifest_file: &ManifestFile) {
+    exec!([&srp, "git add {:?}", manifest_file.path()]);
+    if let Some(root) = manifest_file.workspace_root() {
+        exec!([&srp, "git add {:?}", root.path()]);
+    }
+}
+
+/// Publish a package to crates.io or another registry, or
+/// just dry run it.
+///
+/// When wet, a version which the registry already has counts
+/// as published, so that a release interrupted after its
+/// upload can be resumed.
+pub fn cargo_publish(package_path: &Path, registry: Option<&str>, moist: MoistMeter) {
+    match moist {
+        MoistMeter::Dry => info!("Running cargo publish dry run"),
+        MoistMeter::Wet => info!("Publishing to {}", registry.unwrap_or("crates.io")),
+    };
+    let (status, stderr) = exec_stderr(
+        package_path, cargo_publish_command(registry, moist));
+    let uploaded = stderr.contains("already uploaded") 
+        || stderr.contains("already exists");
+    if status.is_err() && moist == MoistMeter::Wet && uploaded {
+        info!("Already published, so carrying on");
+        return;
+    }
+    status.ekill();
+}
+
+/// The cargo command to publish a package, or just dry run it.
+pub fn cargo_publish_command(registry: Option<&str>, moist: MoistMeter) -> String {
+    let registry_arg = registry
+        .map(|registry| format!(" --registry {}", registry))
+        .unwrap_or_default();
+    match moist {
+        MoistMeter::Dry => format!(
+            "cargo publish  --color always --locked --dry-run --allow-dirty{}", registry_arg),
+        MoistMeter::Wet => format!(
+            "cargo publish --color always --locked{}", registry_arg),
+    }
+}
+
+/// Get the subject of the commit checked out in a repo.
+fn head_subject(repo: &Path) -> String {
+    exec!([&repo, r#"git log --format="%s" -n 1"#] | (preadln))
+}
+
+/// Whether a tag exists in a repo.
+pub fn tag_exists(repo: &Path, tag: &str) -> bool {
+    exec!([&repo, "git tag -l {}", tag] | (pnonempty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions.iter().map(|v| Version::parse(v).unwrap()).collect()
+    }
+    
+    /// Check a version against releases, and return the error,
+    /// or the previous release it skips versions after.
+    fn order(releases: &[&str], version: &str, allow_lower: bool) -> Result<Option<String>, String> {
+        let releases = versions(releases);
+        order_against(&releases, "a", &Version::parse(version).unwrap(), allow_lower)
+            .map(|prev| prev.map(Version::to_string))
+            .map_err(|e| e.to_string())
+    }
+    
+    #[test]
+    fn order_already_released() {
+        let releases = &["1.0.0", "1.1.0"];
+        assert_eq!(order(releases, "1.0.0", false), 
+            Err("a 1.0.0 was already released".to_owned()));
+        assert_eq!(order(releases, "1.0.0", true), 
+            Err("a 1.0.0 was already released".to_owned()));
+    }
+    
+    #[test]
+    fn order_lower() {
+        let releases = &["0.1.0", "1.0.0", "1.1.0", "2.0.0"];
+        assert_eq!(order(releases, "1.1.1", false), 
+            Err("a 1.1.1 is lower than the newest release 2.0.0, pass \
+                --allow-lower to release it for a maintenance line".to_owned()));
+        assert_eq!(order(releases, "1.1.1", true), Ok(None));
+        assert_eq!(order(releases, "0.1.1", true), Ok(None));
+        // but not below a release of the same maintenance line
+        assert_eq!(order(releases, "1.0.1", true), 
+            Err("a 1.0.1 is lower than the release 1.1.0, which is in the \
+                same maintenance line".to_owned()));
+        assert_eq!(order(&["0.1.0", "0.1.3"], "0.1.2", true), 
+            Err("a 0.1.2 is lower than the release 0.1.3, which is in the \
+                same maintenance line".to_owned()));
+    }
+    
+    #[test]
+    fn order_skipped_versions() {
+        let releases = &["1.0.0", "1.1.0"];
+        assert_eq!(order(&[], "3.0.0", false), Ok(None));
+        for next in &["1.1.1", "1.2.0", "2.0.0", "1.1.1-0", "1.2.0-rc.1", "2.0.0+build"] {
+            assert_eq!(order(releases, next, false), Ok(None), "{}", next);
+        }
+        for skipping in &["1.1.2", "1.3.0", "3.0.0", "1.3.0-rc.1"] {
+            assert_eq!(order(releases, skipping, false), Ok(Some("1.1.0".to_owned())), 
+                "{}", skipping);
+        }
+        // a maintenance release skips from its own line's previous release
+        assert_eq!(order(&["1.0.0", "2.0.0"], "1.0.2", true), Ok(Some("1.0.0".to_owned())));
+        assert_eq!(order(&["1.0.0", "2.0.0"], "1.0.1", true), Ok(None));
+    }
+}
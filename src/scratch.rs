@@ -0,0 +1,105 @@
+//! Scratch repos, which releases are made in, and cleaning
+//! them up.
+//!
+//! Scratch repos are created in a temp directory, along with
+//! the journals of wet releases made in them.
+
+use crate::{
+    util::cli::{
+        parse_var,
+        ResultExt,
+    },
+    journal::Journal,
+};
+use std::{
+    path::{Path, PathBuf},
+    fs::{self, canonicalize},
+    time::{Duration, SystemTime},
+    env,
+};
+use failure::Error;
+
+/// Prefix of scratch repo directory names.
+pub const SCRATCH_PREFIX: &str = "srp-";
+
+/// A scratch repo in the temp directory.
+#[derive(Debug, Clone)]
+pub struct ScratchRepo {
+    pub path: PathBuf,
+    /// Time since the scratch repo was last modified.
+    pub age: Duration,
+    /// Journal of the release made in it, if wet.
+    pub journal: Option<Journal>,
+}
+
+impl ScratchRepo {
+    /// Whether a wet release made in it didn't finish.
+    pub fn is_unfinished(&self) -> bool {
+        self.journal.as_ref()
+            .map(|journal| !journal.is_finished())
+            .unwrap_or(false)
+    }
+}
+
+/// Get the temp directory which scratch repos are created in.
+///
+/// This is `DEET_TMP_DIR` if set, or otherwise a `deet`
+/// directory within the system temp directory.
+pub fn tmp_dir() -> PathBuf {
+    let tmp = parse_var::<PathBuf>("DEET_TMP_DIR").ekill()
+        .unwrap_or_else(|| env::temp_dir().join("deet"));
+    fs::create_dir_all(&tmp).ekill();
+    canonicalize(&tmp).ekill()
+}
+
+/// List the scratch repos in a temp directory, oldest first.
+pub fn list_scratch_repos(tmp: &Path) -> Result<Vec<ScratchRepo>, Error> {
+    let now = SystemTime::now();
+    let mut srps = Vec::new();
+    for entry in fs::read_dir(tmp).map_err(Error::from)? {
+        let entry = entry.map_err(Error::from)?;
+        let is_srp = entry.file_name().to_string_lossy().starts_with(SCRATCH_PREFIX)
+            && entry.file_type().map_err(Error::from)?.is_dir();
+        if !is_srp {
+            continue;
+        }
+
+        let path = entry.path();
+        let modified = entry.metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(Error::from)?;
+        let journal_path = Journal::path_for(&path);
+        let journal = match journal_path.is_file() {
+            true => Some(Journal::open(&journal_path)?),
+            false => None,
+        };
+        srps.push(ScratchRepo {
+            path,
+            age: now.duration_since(modified).unwrap_or_default(),
+            journal,
+        });
+    }
+    srps.sort_by_key(|srp| std::cmp::Reverse(srp.age));
+    Ok(srps)
+}
+
+/// Delete a scratch repo, and its journal if it has one.
+pub fn remove_scratch_repo(srp: &Path) -> Result<(), Error> {
+    fs::remove_dir_all(srp).map_err(Error::from)?;
+    let journal_path = Journal::path_for(srp);
+    if journal_path.is_file() {
+        fs::remove_file(&journal_path).map_err(Error::from)?;
+    }
+    Ok(())
+}
+
+/// Clean up a scratch repo after the process succeeded,
+/// unless `keep`, in which case its path is logged.
+pub fn cleanup_scratch_repo(srp: &Path, keep: bool) {
+    if keep {
+        info!("Scratch repo kept at:\n{:?}", srp);
+    } else {
+        debug!("Deleting scratch repo at:\n{:?}", srp);
+        remove_scratch_repo(srp).ekill();
+    }
+}
@@ -6,12 +6,15 @@ use std::{
 };
 use failure::{Error, format_err};
 
-/// Match on program args.
+/// Match on program args, or on a vec of remaining args.
 macro_rules! match_args {
     (match { $($t:tt)* })=>{{
-        let args0: Vec<String> = std::env::args().collect();
-        let args1: Vec<&str> = args0.iter().map(String::as_str).collect();
-        match &args1[1..] { $($t)* }
+        let args0: Vec<String> = std::env::args().skip(1).collect();
+        match_args!(match args0 { $($t)* })
+    }};
+    (match $args:ident { $($t:tt)* })=>{{
+        let args1: Vec<&str> = $args.iter().map(String::as_str).collect();
+        match &args1[..] { $($t)* }
     }};
 }
 
@@ -29,7 +32,7 @@ macro_rules! match_var {
 macro_rules! kill {
     ($($t:tt)*)=>{{
         error!($($t)*);
-        std::process::exit(1)
+        $crate::leet::exit_failure()
     }};
 }
 
@@ -53,15 +56,42 @@ impl<I, E> ResultExt<I, E> for Result<I, E> {
     }
 }
 
-/// Get and parse env var or abort.
-pub fn parse_var<T: FromStr>(name: &str) -> Result<T, Error>
+/// Get and parse env var, if set.
+pub fn parse_var<T: FromStr>(name: &str) -> Result<Option<T>, Error>
 where
     T::Err: Debug
 {
-    env::var(name)
-        .unwrap_or_else(|_|
-            kill!("missing required env var {:?}", name))
-        .parse::<T>()
-        .map_err(|e| format_err!("failed to parse \
-            env var {:?}:\n{:#?}", name, e))
+    env::var(name).ok()
+        .map(|var| var
+            .parse::<T>()
+            .map_err(|e| format_err!("failed to parse \
+                env var {:?}:\n{:#?}", name, e)))
+        .transpose()
+}
+
+/// Remove a flag from program args, returning whether it
+/// was present.
+pub fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != name);
+    args.len() != len
+}
+
+/// Remove an option and its value from program args, as
+/// either `--name value` or `--name=value`.
+pub fn take_opt(args: &mut Vec<String>, name: &str) -> Result<Option<String>, Error> {
+    let prefix = format!("{}=", name);
+    let i = match args.iter().position(|arg| arg == name || arg.starts_with(&prefix)) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    let arg = args.remove(i);
+    if let Some(value) = arg.strip_prefix(&prefix) {
+        return Ok(Some(value.to_owned()));
+    }
+    if i < args.len() {
+        Ok(Some(args.remove(i)))
+    } else {
+        Err(format_err!("missing value for {}", name))
+    }
 }
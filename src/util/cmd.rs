@@ -1,14 +1,17 @@
 
 //! Shell-like subprocess execution DSL.
 
-use crate::util::cli::ResultExt;
+use crate::{
+    util::cli::ResultExt,
+    leet::print_line,
+};
 use std::{
     io::{Read, Write, BufRead, BufReader, BufWriter},
     path::Path,
     collections::HashMap,
     process::{Command, Child, Stdio, ChildStdout},
     ffi::OsStr,
-    mem::replace,
+    mem::take,
     thread,
 };
 use failure::{Error, format_err};
@@ -145,21 +148,21 @@ fn smart_split<S: AsRef<str>>(input: S) -> Vec<String> {
         } else if c == '\"' {
             quote_mode = !quote_mode;
         } else if c.is_ascii_whitespace() && !quote_mode {
-            if curr_buff.len() > 0 {
-                parts.push(replace(&mut curr_buff, String::new()));
+            if !curr_buff.is_empty() {
+                parts.push(take(&mut curr_buff));
             }
         } else {
             curr_buff.push(c);
         }
     }
     
-    if curr_buff.len() > 0 { parts.push(curr_buff); }
+    if !curr_buff.is_empty() { parts.push(curr_buff); }
     
     parts
 }
 
 /// Spawn a thread to delegate from a `Read` to our
-/// `stdout`.
+/// log output.
 pub fn printout<R>(read: R)
 where
     R: Read + Send + 'static {
@@ -178,7 +181,7 @@ where
                     .map(String::from)
                     .collect::<Vec<_>>())
             {
-                println!("| {}", line);
+                print_line(format_args!("| {}", line));
             }
         })
         .ekill();
@@ -205,7 +208,7 @@ pub fn pnonempty(mut stdout: ChildStdout) -> bool {
     let mut buf = Vec::new();
     stdout.read_to_end(&mut buf).ekill();
     let out = String::from_utf8(buf).ekill();
-    out.trim().len() > 0
+    !out.trim().is_empty()
 }
 
 /// Join a process, return its exit code.
@@ -218,6 +221,41 @@ pub fn pjoin(mut child: Child) -> Result<(), Error> {
     }
 }
 
+/// Run a command to completion, printing its output like 
+/// `exec!`, and return whether it succeeded, along with what
+/// it printed to stderr. Unlike `exec!`, environment variables
+/// can't be set in the command.
+pub fn exec_stderr<P, C>(workdir: P, cmd: C) -> (Result<(), Error>, String)
+where
+    P: AsRef<Path>,
+    C: AsRef<str>,
+{
+    let parts = smart_split(cmd);
+    let (program, args) = parts.split_first()
+        .ok_or_else(|| format_err!(
+            "cannot find program part of command"))
+        .ekill();
+    let mut sys_cmd = Command::new(program);
+    sys_cmd
+        .args(args)
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .current_dir(&workdir);
+    trace!("Executing command:\n{:?}", sys_cmd);
+    let mut subproc = sys_cmd.spawn().ekill();
+    printout(subproc.stdout.take().unwrap());
+    
+    let mut stderr = String::new();
+    for line in BufReader::new(subproc.stderr.take().unwrap()).lines() {
+        let line = line.ekill();
+        print_line(format_args!("| {}", line));
+        stderr.push_str(&line);
+        stderr.push('\n');
+    }
+    (pjoin(subproc), stderr)
+}
+
 pub fn exec_command<I, P, C>(
     input: I, workdir: P, cmd: C)
     -> (Child, ChildStdout)
@@ -287,7 +325,7 @@ where
                         to subprocess:\
                         {}", e, sys_cmd_str))
                     .ekill();
-                if chunk.len() == 0 {
+                if chunk.is_empty() {
                     // Quoting [the docs](https://doc.rust-lang.org/std/io/trait.BufRead.html#tymethod.fill_buf)
                     //
                     // > An empty buffer returned indicates that the stream has reached EOF.
@@ -1,7 +1,10 @@
 
 //! Display helpers.
 
-use std::fmt::{self, Formatter, Display};
+use std::{
+    fmt::{self, Formatter, Display},
+    time::Duration,
+};
 
 /// Sequence of `Display` which `Display`s each on own line.
 #[derive(Debug, Clone)]
@@ -20,7 +23,7 @@ where
         for elem in self.0.clone() {
             buf.push_str(&format!("{}\n", elem));
         }
-        if buf.len() > 0 {
+        if !buf.is_empty() {
             buf.pop();
         }
         f.write_str(&buf)
@@ -49,9 +52,28 @@ where
         for elem in self.0.clone() {
             buf.push_str(&format!("{}\n", (self.1)(elem)));
         }
-        if buf.len() > 0 {
+        if !buf.is_empty() {
             buf.pop();
         }
         f.write_str(&buf)
     }
+}
+
+/// Duration which `Display`s coarsely, in days, hours, and
+/// minutes.
+#[derive(Debug, Copy, Clone)]
+pub struct Age(pub Duration);
+
+impl Display for Age {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let minutes = self.0.as_secs() / 60;
+        let (days, hours, minutes) = (minutes / (60 * 24), minutes / 60 % 24, minutes % 60);
+        if days > 0 {
+            write!(f, "{}d {}h", days, hours)
+        } else if hours > 0 {
+            write!(f, "{}h {}m", hours, minutes)
+        } else {
+            write!(f, "{}m", minutes)
+        }
+    }
 }
\ No newline at end of file
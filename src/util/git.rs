@@ -15,6 +15,11 @@ pub struct Commit {
     pub pretty: String,
 }
 
+/// Get the root of the repo containing a path.
+pub fn toplevel<P: AsRef<Path>>(path: P) -> String {
+    exec!([path.as_ref(), "git rev-parse --show-toplevel"] | (preadln))
+}
+
 /// List the git commits which effect a file/directory.
 pub fn follow<P0, P1>(repo: P0, path: P1) -> Vec<Commit> 
 where
@@ -0,0 +1,118 @@
+//! Minimal JSON output.
+
+use std::fmt::{self, Formatter, Display};
+
+/// A JSON value, which Displays pretty-printed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    String(String),
+    Array(Vec<Json>),
+    /// Object, with keys in insertion order.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Build an object from key/value pairs.
+    pub fn object<I, K>(fields: I) -> Self
+    where
+        I: IntoIterator<Item=(K, Json)>,
+        K: Into<String>,
+    {
+        Json::Object(fields.into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect())
+    }
+
+    fn write(&self, f: &mut Formatter, indent: usize) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => Display::fmt(&b, f),
+            Json::String(ref s) => write_str(f, s),
+            Json::Array(ref elems) if elems.is_empty() => f.write_str("[]"),
+            Json::Array(ref elems) => {
+                f.write_str("[\n")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    write_indent(f, indent + 1)?;
+                    elem.write(f, indent + 1)?;
+                    if i + 1 < elems.len() {
+                        f.write_str(",")?;
+                    }
+                    f.write_str("\n")?;
+                }
+                write_indent(f, indent)?;
+                f.write_str("]")
+            },
+            Json::Object(ref fields) if fields.is_empty() => f.write_str("{}"),
+            Json::Object(ref fields) => {
+                f.write_str("{\n")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    write_indent(f, indent + 1)?;
+                    write_str(f, key)?;
+                    f.write_str(": ")?;
+                    value.write(f, indent + 1)?;
+                    if i + 1 < fields.len() {
+                        f.write_str(",")?;
+                    }
+                    f.write_str("\n")?;
+                }
+                write_indent(f, indent)?;
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+fn write_indent(f: &mut Formatter, indent: usize) -> fmt::Result {
+    for _ in 0..indent {
+        f.write_str("  ")?;
+    }
+    Ok(())
+}
+
+fn write_str(f: &mut Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        };
+    }
+    f.write_str("\"")
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self { Json::Bool(b) }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self { Json::String(s.to_owned()) }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self { Json::String(s) }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(option: Option<T>) -> Self {
+        option.map(Into::into).unwrap_or(Json::Null)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(elems: Vec<T>) -> Self {
+        Json::Array(elems.into_iter().map(Into::into).collect())
+    }
+}
@@ -7,4 +7,8 @@ pub mod cmd;
 pub mod display;
 pub mod hex;
 pub mod path;
-pub mod git;
\ No newline at end of file
+pub mod git;
+pub mod json;
+pub mod date;
+#[cfg(test)]
+pub mod testing;
\ No newline at end of file
@@ -1,8 +1,12 @@
 
 //! File-system path utilities.
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    fs,
+};
 use failure::{Error, format_err};
+use regex::Regex;
 
 pub fn path_rebase<P0, P1, P2>(full: P0, old_base: P1, new_base: P2) -> Result<PathBuf, Error> 
 where
@@ -20,4 +24,47 @@ where
             new_base.as_ref(), 
             e))
         .map(|suffix| new_base.as_ref().join(suffix))
-}
\ No newline at end of file
+}
+
+/// Expand a glob pattern, relative to `base`, into the
+/// directories it matches.
+///
+/// Only `*` and `?` wildcards within a path component are 
+/// supported, which is enough for cargo workspace members.
+pub fn glob_dirs<P, S>(base: P, pattern: S) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let mut found = vec![base.as_ref().to_owned()];
+    for part in pattern.as_ref().split('/').filter(|p| !p.is_empty()) {
+        if !part.contains(['*', '?']) {
+            found = found.into_iter()
+                .map(|dir| dir.join(part))
+                .filter(|dir| dir.is_dir())
+                .collect();
+            continue;
+        }
+        
+        let pat = format!("^{}$", regex::escape(part)
+            .replace(r"\*", ".*")
+            .replace(r"\?", "."));
+        let pat = Regex::new(&pat).map_err(Error::from)?;
+        
+        let mut next = Vec::new();
+        for dir in found {
+            for entry in fs::read_dir(&dir).map_err(Error::from)? {
+                let entry = entry.map_err(Error::from)?;
+                let matches = entry.file_name().to_str()
+                    .map(|name| pat.is_match(name))
+                    .unwrap_or(false);
+                if matches && entry.path().is_dir() {
+                    next.push(entry.path());
+                }
+            }
+        }
+        found = next;
+    }
+    found.sort();
+    Ok(found)
+}
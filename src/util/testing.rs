@@ -0,0 +1,55 @@
+//! Fixtures for unit tests.
+
+use crate::util::cli::ResultExt;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A fresh directory for a test, deleted when dropped.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Create a directory for a test, named so that no other
+    /// test or run shares it.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("deet-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir {
+            path: fs::canonicalize(&path).unwrap(),
+        }
+    }
+
+    /// Get the directory's path.
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Write a file within the directory, creating its parent
+    /// directories, and return its path.
+    pub fn write(&self, file: &str, data: &str) -> PathBuf {
+        let path = self.path.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    /// Read a file within the directory, or nothing if it
+    /// doesn't exist.
+    pub fn read(&self, file: &str) -> String {
+        fs::read_to_string(self.path.join(file)).unwrap_or_default()
+    }
+
+    /// Run a git command in the directory, as a fixed author.
+    pub fn git(&self, args: &str) {
+        exec!([&self.path, "git -c user.name=deet -c user.email=deet@localhost {}", args]);
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,128 @@
+//! Checking a release to a registry with a local file-based
+//! index, configured in cargo's config.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Run a command in a directory, with a fixed git identity,
+/// and return whether it succeeded.
+fn run(dir: &Path, program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "deet")
+        .env("GIT_AUTHOR_EMAIL", "deet@localhost")
+        .env("GIT_COMMITTER_NAME", "deet")
+        .env("GIT_COMMITTER_EMAIL", "deet@localhost")
+        .env("DEET_TMP_DIR", dir.parent().unwrap().join("tmp"))
+        .status()
+        .unwrap()
+        .success()
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    assert!(run(dir, "git", args), "git {:?} failed", args);
+}
+
+/// Create a registry index, a remote, and a repo with a package
+/// which may only be published to that registry, and return the
+/// test's directory and the repo. Files are added to the repo
+/// before its first commit.
+fn setup(name: &str, files: &[(&str, &str)]) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir()
+        .join(format!("deet-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    let (index, remote, repo) = (dir.join("index"), dir.join("remote.git"), dir.join("repo"));
+    for path in &[&index, &remote, &repo.join("src"), &repo.join(".cargo")] {
+        fs::create_dir_all(path).unwrap();
+    }
+
+    // a registry index, which a dry run never uploads to
+    fs::write(index.join("config.json"), format!(
+        "{{\"dl\": \"file://{}/{{crate}}-{{version}}.crate\", \
+        \"api\": \"http://127.0.0.1:1\"}}", dir.display())).unwrap();
+    git(&index, &["init", "-q"]);
+    git(&index, &["add", "-A"]);
+    git(&index, &["commit", "-qm", "index"]);
+
+    fs::write(repo.join(".cargo/config.toml"), format!(
+        "[registries.local]\nindex = \"file://{}\"\n", index.display())).unwrap();
+    fs::write(repo.join("Cargo.toml"), "\
+[package]
+name = \"deet-registry-test\"
+version = \"0.0.0-AFTER\"
+edition = \"2018\"
+description = \"Test package\"
+license = \"MIT\"
+publish = [\"local\"]
+
+[workspace]
+").unwrap();
+    fs::write(repo.join("src/lib.rs"), "").unwrap();
+    fs::write(repo.join("CHANGELOG.md"), "## 0.1.0\n\n- Initial release.\n").unwrap();
+    fs::write(repo.join("deet.toml"), "checks = []\n").unwrap();
+    fs::write(repo.join(".gitignore"), "target\n").unwrap();
+    for (file, data) in files {
+        let path = repo.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+    git(&remote, &["init", "-q", "--bare"]);
+    git(&repo, &["init", "-q", "-b", "main"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-qm", "init"]);
+    git(&repo, &["remote", "add", "origin", remote.to_str().unwrap()]);
+    git(&repo, &["push", "-q", "origin", "main"]);
+    (dir, repo)
+}
+
+#[test]
+fn check_with_file_registry() {
+    let (dir, repo) = setup("registry", &[]);
+    let checked = run(&repo, env!("CARGO_BIN_EXE_deet"),
+        &["check", ".", "0.1.0", "--registry", "local"]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(checked, "deet check failed");
+}
+
+#[test]
+fn plan_released_dep_from_registry() {
+    let (dir, repo) = setup("registry-dep", &[
+        ("dep/Cargo.toml", "\
+[package]
+name = \"deet-registry-dep\"
+version = \"0.2.0\"
+edition = \"2018\"
+"),
+        ("dep/src/lib.rs", ""),
+    ]);
+    // the package depends on a local dep released before this run
+    git(&repo, &["tag", "deet-registry-dep-v0.2.0"]);
+    let manifest = fs::read_to_string(repo.join("Cargo.toml")).unwrap()
+        .replace("[workspace]", "\
+[dependencies]
+deet-registry-dep = { path = \"dep\" }
+
+[workspace]");
+    fs::write(repo.join("Cargo.toml"), manifest).unwrap();
+    git(&repo, &["commit", "-qam", "depend on dep"]);
+    git(&repo, &["push", "-q", "origin", "main", "--tags"]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_deet"))
+        .args(["plan", ".", "0.1.0", "--registry", "local", "--format", "json"])
+        .current_dir(&repo)
+        .env("DEET_TMP_DIR", dir.join("tmp"))
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "deet plan failed:\n{}",
+        String::from_utf8_lossy(&output.stderr));
+    let plan = String::from_utf8(output.stdout).unwrap()
+        .split_whitespace()
+        .collect::<String>();
+    assert!(plan.contains("\"version_req\":\"^0.2.0\",\"registry\":\"local\""),
+        "dep not required from the registry:\n{}", plan);
+}